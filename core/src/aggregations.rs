@@ -4,6 +4,11 @@ use nanoserde::{DeBin, SerBin};
 pub fn aggregate(kind: &Aggregation, components: &[f64]) -> f64 {
     use Aggregation::*;
 
+    // Nodes without incoming connections, as in sparse initial topologies, only have their bias
+    if components.is_empty() {
+        return 0.;
+    }
+
     match kind {
         Product => components
             .iter()
@@ -22,23 +27,11 @@ pub fn aggregate(kind: &Aggregation, components: &[f64]) -> f64 {
             .map(|component| component.abs())
             .collect::<Vec<f64>>()),
         Median => {
-            use std::cmp::Ordering;
-
-            if components.is_empty() {
-                return 0.;
-            }
-
             let mut sorted = components.to_vec();
-            sorted.sort_by(|a, b| {
-                if a < b {
-                    Ordering::Less
-                } else {
-                    Ordering::Greater
-                }
-            });
+            sorted.sort_by(|a, b| a.total_cmp(b));
 
             let length = sorted.len();
-            let is_length_even = length % 2 == 0;
+            let is_length_even = length.is_multiple_of(2);
             let median_index = if is_length_even {
                 length / 2 - 1
             } else {
@@ -0,0 +1,70 @@
+use godot::prelude::*;
+
+use crate::{
+    Configuration, EnumConversion, GridDimension, InitialTopology, OffspringAllocation, RunMode,
+};
+
+#[godot_api]
+impl RefCountedVirtual for Configuration {
+    fn init(_base: Base<RefCounted>) -> Self {
+        Self::default()
+    }
+}
+
+#[godot_api]
+impl Configuration {
+    #[func]
+    fn set_fitness_goal(&mut self, to: Variant) {
+        if to.is_nil() {
+            self.fitness_goal = None
+        } else {
+            self.fitness_goal = Some(f64::from_variant(&to))
+        }
+    }
+
+    /// 0 for explicit fitness sharing, 1 for softmax, 2 for rank based allocation
+    #[func]
+    fn set_offspring_allocation(&mut self, to: u8) {
+        self.offspring_allocation = OffspringAllocation::from(to)
+    }
+
+    #[func]
+    fn get_offspring_allocation(&self) -> u8 {
+        self.offspring_allocation.to()
+    }
+
+    /// 0 fully connected, 1 unconnected, 2 a single random input (FS-NEAT),
+    /// 3 a random fraction of connections, 4 one hidden layer
+    #[func]
+    fn set_initial_topology(&mut self, to: u8) {
+        self.initial_topology = InitialTopology::from(to)
+    }
+
+    #[func]
+    fn get_initial_topology(&self) -> u8 {
+        self.initial_topology.to()
+    }
+
+    /// 0 for species based NEAT, 1 for MAP-Elites
+    #[func]
+    fn set_run_mode(&mut self, to: u8) {
+        self.run_mode = RunMode::from(to)
+    }
+
+    #[func]
+    fn get_run_mode(&self) -> u8 {
+        self.run_mode.to()
+    }
+
+    /// Adds an axis to the MAP-Elites grid, splitting `[min, max]` into `cells` cells
+    #[func]
+    fn add_map_elites_dimension(&mut self, min: f64, max: f64, cells: u32) {
+        self.map_elites_dimensions
+            .push(GridDimension::new(min, max, cells))
+    }
+
+    #[func]
+    fn clear_map_elites_dimensions(&mut self) {
+        self.map_elites_dimensions.clear()
+    }
+}
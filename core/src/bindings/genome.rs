@@ -0,0 +1,85 @@
+use godot::prelude::*;
+
+use super::Pack;
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct Genome {
+    pub genome: crate::Genome,
+}
+
+#[godot_api]
+impl RefCountedVirtual for Genome {
+    fn to_string(&self) -> GodotString {
+        format!("{:?}", self.genome).into()
+    }
+}
+
+#[godot_api]
+impl Genome {
+    #[func]
+    pub fn get_id(&self) -> i64 {
+        self.genome.id().into()
+    }
+
+    #[func]
+    pub fn get_inputs(&self) -> u32 {
+        self.genome.input_count()
+    }
+
+    #[func]
+    pub fn get_outputs(&self) -> u32 {
+        self.genome.output_count()
+    }
+
+    /// Whether recurrent and self-loop connections are allowed
+    #[func]
+    pub fn get_recurrent(&self) -> bool {
+        self.genome.is_recurrent()
+    }
+
+    #[func]
+    pub fn fitness(&self) -> f64 {
+        self.genome.fitness()
+    }
+
+    /// What selection ranked the genome by, the fitness unless novelty or objectives were scored
+    #[func]
+    pub fn score(&self) -> f64 {
+        self.genome.score()
+    }
+
+    /// The behavior characterization of the last evaluation, empty without novelty search
+    #[func]
+    pub fn get_behavior(&self) -> PackedFloat64Array {
+        self.genome.behavior.clone().pack()
+    }
+
+    /// The objectives of the last evaluation, empty without multi-objective optimization
+    #[func]
+    pub fn get_objectives(&self) -> PackedFloat64Array {
+        self.genome.objectives.clone().pack()
+    }
+
+    #[func]
+    pub fn to_bytes(&self) -> PackedByteArray {
+        self.genome.to_bytes().pack()
+    }
+
+    #[func]
+    pub fn from_bytes(bytes: PackedByteArray) -> Variant {
+        match crate::Genome::from_bytes(&bytes.to_vec()) {
+            Ok(genome) => Gd::new(Genome { genome }).to_variant(),
+            Err(e) => {
+                godot_error!("deserializing failed: {e:#?}");
+                Variant::nil()
+            }
+        }
+    }
+
+    /// Human readable listing of every gene, disabled connections included
+    #[func]
+    pub fn to_text(&self) -> GodotString {
+        self.genome.to_text().into()
+    }
+}
@@ -0,0 +1,46 @@
+//! Godot classes wrapping the engine independent core
+
+use godot::prelude::*;
+
+mod configuration;
+mod genome;
+mod neat;
+mod network;
+mod species;
+mod statistics;
+mod substrate;
+
+struct Lib;
+
+#[gdextension]
+unsafe impl ExtensionLibrary for Lib {}
+
+pub(crate) trait Pack {
+    type Packed;
+
+    fn pack(self) -> Self::Packed;
+}
+
+impl Pack for Vec<u8> {
+    type Packed = PackedByteArray;
+
+    fn pack(self) -> PackedByteArray {
+        let mut arr = PackedByteArray::new();
+        for i in self.into_iter() {
+            arr.push(i)
+        }
+        arr
+    }
+}
+
+impl Pack for Vec<f64> {
+    type Packed = PackedFloat64Array;
+
+    fn pack(self) -> PackedFloat64Array {
+        let mut arr = PackedFloat64Array::new();
+        for f in self.into_iter() {
+            arr.push(f)
+        }
+        arr
+    }
+}
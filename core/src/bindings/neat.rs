@@ -0,0 +1,613 @@
+use godot::engine::{ProjectSettings, WorkerThreadPool};
+use godot::prelude::*;
+use std::sync::Mutex;
+
+use super::genome::Genome;
+use super::network::Network;
+use super::species::Species;
+use super::statistics::{generation_dictionary, Statistics};
+use super::substrate::Substrate;
+use super::Pack;
+use crate::{Configuration, Evaluation, Evaluator};
+
+/// Reads what a GDScript evaluation function returned, either the fitness alone or a
+/// dictionary with the `fitness`, a `behavior` array for novelty search and an `objectives`
+/// array for multi-objective optimization
+fn evaluation_from_variant(variant: &Variant) -> Evaluation {
+    if variant.get_type() != VariantType::Dictionary {
+        return Evaluation::from(f64::from_variant(variant));
+    }
+
+    let dictionary = Dictionary::from_variant(variant);
+    let array = |key: &str| {
+        dictionary.get(key).map_or(vec![], |array| {
+            PackedFloat64Array::from_variant(&array).to_vec()
+        })
+    };
+    let objectives = array("objectives");
+    let fitness = dictionary.get("fitness").map_or_else(
+        || objectives.iter().sum(),
+        |fitness| f64::from_variant(&fitness),
+    );
+    Evaluation {
+        fitness,
+        behavior: array("behavior"),
+        objectives,
+    }
+}
+
+/// Scores networks by calling a GDScript function with each of them
+struct CallableEvaluator<'a>(&'a Callable);
+
+impl Evaluator for CallableEvaluator<'_> {
+    fn evaluate(&mut self, networks: &mut [crate::Network]) -> Vec<f64> {
+        self.evaluate_all(networks)
+            .into_iter()
+            .map(|evaluation| evaluation.fitness)
+            .collect()
+    }
+
+    fn evaluate_all(&mut self, networks: &mut [crate::Network]) -> Vec<Evaluation> {
+        networks
+            .iter()
+            .map(|network| {
+                let network = Gd::new(Network {
+                    network: network.clone(),
+                });
+                evaluation_from_variant(&self.0.callv(varray![network]))
+            })
+            .collect()
+    }
+}
+
+/// Networks of one generation, evaluated by `WorkerThreadPool` group tasks
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+struct WorkerEvaluation {
+    fitness_fn: Callable,
+    networks: Vec<Gd<Network>>,
+    evaluations: Mutex<Vec<Evaluation>>,
+}
+
+#[godot_api]
+impl WorkerEvaluation {
+    /// Called by the pool with the index of the network to evaluate
+    #[func]
+    fn evaluate(&self, index: u32) {
+        let network = self.networks[index as usize].share();
+        let evaluation = evaluation_from_variant(&self.fitness_fn.callv(varray![network]));
+        self.evaluations.lock().unwrap()[index as usize] = evaluation;
+    }
+}
+
+/// Calls the GDScript function from the engine's worker threads, it needs to be thread safe
+struct WorkerPoolEvaluator<'a>(&'a Callable);
+
+impl Evaluator for WorkerPoolEvaluator<'_> {
+    fn evaluate(&mut self, networks: &mut [crate::Network]) -> Vec<f64> {
+        self.evaluate_all(networks)
+            .into_iter()
+            .map(|evaluation| evaluation.fitness)
+            .collect()
+    }
+
+    fn evaluate_all(&mut self, networks: &mut [crate::Network]) -> Vec<Evaluation> {
+        let evaluation = Gd::new(WorkerEvaluation {
+            fitness_fn: self.0.clone(),
+            networks: networks
+                .iter()
+                .map(|network| {
+                    Gd::new(Network {
+                        network: network.clone(),
+                    })
+                })
+                .collect(),
+            evaluations: Mutex::new(vec![Evaluation::default(); networks.len()]),
+        });
+
+        let mut pool = WorkerThreadPool::singleton();
+        let task = pool.add_group_task(
+            Callable::from_object_method(evaluation.share(), "evaluate"),
+            networks.len() as i64,
+            -1,
+            false,
+            "NEAT fitness evaluation".into(),
+        );
+        pool.wait_for_group_task_completion(task);
+
+        let evaluations = evaluation.bind().evaluations.lock().unwrap().clone();
+        evaluations
+    }
+}
+
+/// Hands all networks of a generation to a GDScript function at once
+struct BatchEvaluator<'a>(&'a Callable);
+
+impl Evaluator for BatchEvaluator<'_> {
+    fn evaluate(&mut self, networks: &mut [crate::Network]) -> Vec<f64> {
+        self.evaluate_all(networks)
+            .into_iter()
+            .map(|evaluation| evaluation.fitness)
+            .collect()
+    }
+
+    fn evaluate_all(&mut self, networks: &mut [crate::Network]) -> Vec<Evaluation> {
+        let mut batch: Array<Gd<Network>> = Array::new();
+        for network in networks.iter() {
+            batch.push(Gd::new(Network {
+                network: network.clone(),
+            }));
+        }
+
+        let result = self.0.callv(varray![batch]);
+        if result.get_type() == VariantType::PackedFloat64Array {
+            return PackedFloat64Array::from_variant(&result)
+                .to_vec()
+                .into_iter()
+                .map(Evaluation::from)
+                .collect();
+        }
+
+        VariantArray::from_variant(&result)
+            .iter_shared()
+            .map(|evaluation| evaluation_from_variant(&evaluation))
+            .collect()
+    }
+}
+
+/// The evaluator picked by the properties of `NEAT`
+enum GodotEvaluator<'a> {
+    Single(CallableEvaluator<'a>),
+    WorkerPool(WorkerPoolEvaluator<'a>),
+    Batch(BatchEvaluator<'a>),
+}
+
+impl<'a> GodotEvaluator<'a> {
+    fn new(
+        fitness_fn: &'a Callable,
+        batch_fitness_fn: &'a Callable,
+        use_worker_threads: bool,
+    ) -> Self {
+        if !batch_fitness_fn.is_null() {
+            GodotEvaluator::Batch(BatchEvaluator(batch_fitness_fn))
+        } else if use_worker_threads {
+            GodotEvaluator::WorkerPool(WorkerPoolEvaluator(fitness_fn))
+        } else {
+            GodotEvaluator::Single(CallableEvaluator(fitness_fn))
+        }
+    }
+}
+
+impl Evaluator for GodotEvaluator<'_> {
+    fn evaluate(&mut self, networks: &mut [crate::Network]) -> Vec<f64> {
+        match self {
+            GodotEvaluator::Single(evaluator) => evaluator.evaluate(networks),
+            GodotEvaluator::WorkerPool(evaluator) => evaluator.evaluate(networks),
+            GodotEvaluator::Batch(evaluator) => evaluator.evaluate(networks),
+        }
+    }
+
+    fn evaluate_all(&mut self, networks: &mut [crate::Network]) -> Vec<Evaluation> {
+        match self {
+            GodotEvaluator::Single(evaluator) => evaluator.evaluate_all(networks),
+            GodotEvaluator::WorkerPool(evaluator) => evaluator.evaluate_all(networks),
+            GodotEvaluator::Batch(evaluator) => evaluator.evaluate_all(networks),
+        }
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct NEAT {
+    #[export(get, set)]
+    inputs: u32,
+    #[export(get, set)]
+    outputs: u32,
+    /// Returns the fitness of a network, or a dictionary with the `fitness` and a `behavior`
+    /// array for novelty search or an `objectives` array for multi-objective optimization
+    #[export(get, set)]
+    fitness_fn: Callable,
+    #[export(get, set)]
+    reporter_fn: Callable,
+    /// Receives an array with every network of the generation and returns
+    /// a `PackedFloat64Array` of their fitnesses in the same order, used instead of `fitness_fn` when set.
+    /// For novelty search it returns an array of the dictionaries `fitness_fn` would return
+    #[export(get, set)]
+    batch_fitness_fn: Callable,
+    /// Dispatches `fitness_fn` calls through `WorkerThreadPool`, so it has to be thread safe,
+    /// calling into the extension from those threads is why gdext is built with `experimental-threads`
+    #[export(get, set)]
+    use_worker_threads: bool,
+    #[export(get, set)]
+    configuration: Gd<Configuration>,
+    /// Runs initialized with the same seed and configuration evolve identically,
+    /// regardless of the engine's global `seed()`. Picked from the system clock unless set
+    #[export(get, set)]
+    seed: i64,
+    neat: crate::NEAT,
+    /// The best fitness reported by `new_best_genome` during the current run
+    best_fitness: Option<f64>,
+    #[base]
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl RefCountedVirtual for NEAT {
+    fn init(base: Base<Self::Base>) -> Self {
+        let neat = crate::NEAT::default();
+        NEAT {
+            inputs: 0,
+            outputs: 0,
+            fitness_fn: Callable::default(),
+            reporter_fn: Callable::default(),
+            batch_fitness_fn: Callable::default(),
+            use_worker_threads: false,
+            configuration: Gd::new_default(),
+            seed: neat.seed() as i64,
+            neat,
+            best_fitness: None,
+            base,
+        }
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct StartResult {
+    #[export(get, set)]
+    pub network: Gd<Network>,
+    #[export(get, set)]
+    pub best_fitness: f64,
+    /// Genomes of the final generation no other genome beats in every objective,
+    /// empty unless objectives were evaluated
+    #[export(get, set)]
+    pub pareto_front: Array<Gd<Genome>>,
+    /// Networks of the final MAP-Elites archive keyed by their cell, empty unless that run mode was used
+    #[export(get, set)]
+    pub elites: Dictionary,
+}
+
+#[godot_api]
+impl StartResult {}
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct BestResult {
+    #[export(get, set)]
+    pub best_id: i64,
+    #[export(get, set)]
+    pub best_fitness: f64,
+}
+
+#[godot_api]
+impl BestResult {}
+
+#[godot_api]
+impl NEAT {
+    /// A generation was evaluated, `stats` has the keys of `Statistics.get_generations()`
+    #[signal]
+    fn generation_finished(generation: u32, stats: Dictionary);
+
+    /// A genome beat the best fitness seen so far in this run
+    #[signal]
+    fn new_best_genome(genome: Gd<Genome>, fitness: f64);
+
+    #[signal]
+    fn species_created(id: u32);
+
+    #[signal]
+    fn species_extinct(id: u32);
+
+    /// The generation limit or the fitness goal has been reached
+    #[signal]
+    fn training_finished(result: Gd<StartResult>);
+
+    /// Creates and evaluates the initial population, discarding any previous run
+    #[func]
+    pub fn initialize(&mut self) {
+        self.sync();
+        self.best_fitness = None;
+        self.neat.initialize(&mut GodotEvaluator::new(
+            &self.fitness_fn,
+            &self.batch_fitness_fn,
+            self.use_worker_threads,
+        ));
+        self.emit_progress();
+    }
+
+    /// Seeds the initial population with mutated copies of the given genome
+    #[func]
+    pub fn add_seed_genome(&mut self, genome: Gd<Genome>) {
+        self.neat.add_seed(genome.bind().genome.clone());
+    }
+
+    /// Seeds the initial population with mutated copies of the given network
+    #[func]
+    pub fn add_seed_network(&mut self, network: Gd<Network>) {
+        self.sync();
+        self.neat.add_seed_network(&network.bind().network);
+    }
+
+    /// Goes back to creating the initial population from scratch
+    #[func]
+    pub fn clear_seeds(&mut self) {
+        self.neat.clear_seeds();
+    }
+
+    /// Evolves CPPNs painting the weights of the substrate, `fitness_fn` gets the painted networks
+    #[func]
+    pub fn set_substrate(&mut self, substrate: Gd<Substrate>) {
+        self.neat.substrate = Some(substrate.bind().substrate.clone());
+    }
+
+    /// Goes back to evolving the evaluated networks directly
+    #[func]
+    pub fn clear_substrate(&mut self) {
+        self.neat.substrate = None;
+    }
+
+    /// The network a genome encodes, painted on the substrate when one is set
+    #[func]
+    pub fn get_network(&self, genome: Gd<Genome>) -> Gd<Network> {
+        Gd::new(Network {
+            network: self.neat.network(&genome.bind().genome),
+        })
+    }
+
+    /// Runs a single generation: speciation, reproduction and evaluation
+    #[func]
+    pub fn step(&mut self) {
+        self.sync();
+        let previous_species: Vec<u32> = self.neat.species_set.species().keys().copied().collect();
+        self.neat.step(&mut GodotEvaluator::new(
+            &self.fitness_fn,
+            &self.batch_fitness_fn,
+            self.use_worker_threads,
+        ));
+        self.reporter_fn.callv(varray![self.neat.generation()]);
+
+        // Speciation pushes one entry to the history, so a single entry means a new species,
+        // even when it took over the id of an extinct one
+        let species = self.neat.species_set.species();
+        let extinct: Vec<u32> = previous_species
+            .into_iter()
+            .filter(|id| {
+                species
+                    .get(id)
+                    .map_or(true, |species| species.fitness_history().len() == 1)
+            })
+            .collect();
+        let created: Vec<u32> = species
+            .iter()
+            .filter(|(_, species)| species.fitness_history().len() == 1)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in extinct {
+            self.base
+                .emit_signal("species_extinct".into(), &[id.to_variant()]);
+        }
+        for id in created {
+            self.base
+                .emit_signal("species_created".into(), &[id.to_variant()]);
+        }
+
+        self.emit_progress();
+    }
+
+    /// Whether the generation limit or the fitness goal has been reached
+    #[func]
+    pub fn is_finished(&mut self) -> bool {
+        self.sync();
+        self.neat.is_finished()
+    }
+
+    /// Runs the whole evolution process at once
+    #[func]
+    pub fn start(&mut self) -> Gd<StartResult> {
+        self.initialize();
+        while !self.is_finished() {
+            self.step();
+        }
+
+        self.get_result()
+    }
+
+    /// The best network ever seen, taken from the hall of fame unless it is disabled
+    #[func]
+    pub fn get_result(&self) -> Gd<StartResult> {
+        let (network, best_fitness) = self.neat.get_result();
+        Gd::new(StartResult {
+            network: Gd::new(Network { network }),
+            best_fitness,
+            pareto_front: self.get_pareto_front(),
+            elites: self.get_elite_networks(),
+        })
+    }
+
+    /// Genomes of the current generation no other genome beats in every objective
+    #[func]
+    pub fn get_pareto_front(&self) -> Array<Gd<Genome>> {
+        let mut genomes = Array::new();
+        for genome in self.neat.pareto_front() {
+            genomes.push(Gd::new(Genome {
+                genome: genome.clone(),
+            }));
+        }
+        genomes
+    }
+
+    #[func]
+    pub fn get_best(&self) -> Gd<BestResult> {
+        let (best_id, best_fitness) = self.neat.get_best();
+        Gd::new(BestResult {
+            best_id: best_id.into(),
+            best_fitness,
+        })
+    }
+
+    /// The generation evolution is currently at, 0 being the initial population
+    #[func]
+    pub fn get_generation(&self) -> u32 {
+        self.neat.generation()
+    }
+
+    /// Genomes of the current generation, keyed by their id
+    #[func]
+    pub fn get_genomes(&self) -> Dictionary {
+        let mut genomes = Dictionary::new();
+        for (id, genome) in self.neat.genomes.genomes() {
+            let genome = Gd::new(Genome {
+                genome: genome.clone(),
+            });
+            genomes.insert(i64::from(*id), genome);
+        }
+        genomes
+    }
+
+    /// Species alive after the last speciation, keyed by their id
+    #[func]
+    pub fn get_species(&self) -> Dictionary {
+        let mut species = Dictionary::new();
+        for (id, s) in self.neat.species_set.species() {
+            species.insert(*id, Gd::new(Species { species: s.clone() }));
+        }
+        species
+    }
+
+    /// The best genomes ever seen in this run, fittest first
+    #[func]
+    pub fn get_hall_of_fame(&self) -> Array<Gd<Genome>> {
+        let mut genomes = Array::new();
+        for genome in self.neat.hall_of_fame.genomes() {
+            genomes.push(Gd::new(Genome {
+                genome: genome.clone(),
+            }));
+        }
+        genomes
+    }
+
+    /// The fittest genome of every MAP-Elites cell, keyed by a `PackedInt32Array` of the cell coordinates
+    #[func]
+    pub fn get_elite_map(&self) -> Dictionary {
+        let mut elites = Dictionary::new();
+        for (cell, genome) in self.neat.elite_map.cells() {
+            let cell: PackedInt32Array = cell.iter().map(|i| *i as i32).collect();
+            let genome = Gd::new(Genome {
+                genome: genome.clone(),
+            });
+            elites.insert(cell, genome);
+        }
+        elites
+    }
+
+    /// The networks of every MAP-Elites elite, keyed like `get_elite_map`
+    #[func]
+    pub fn get_elite_networks(&self) -> Dictionary {
+        let mut networks = Dictionary::new();
+        for (cell, network) in self.neat.elite_networks() {
+            let cell: PackedInt32Array = cell.iter().map(|i| *i as i32).collect();
+            networks.insert(cell, Gd::new(Network { network }));
+        }
+        networks
+    }
+
+    /// Behaviors kept by novelty search because they were novel when first seen
+    #[func]
+    pub fn get_novelty_archive(&self) -> Array<PackedFloat64Array> {
+        let mut behaviors = Array::new();
+        for behavior in self.neat.novelty_archive.behaviors() {
+            behaviors.push(behavior.clone().pack());
+        }
+        behaviors
+    }
+
+    /// Statistics of every generation so far
+    #[func]
+    pub fn get_statistics(&self) -> Gd<Statistics> {
+        Gd::new(Statistics {
+            statistics: self.neat.statistics.clone(),
+        })
+    }
+
+    /// Saves the whole run, so it can be resumed with `load_checkpoint`
+    #[func]
+    pub fn save_checkpoint(&mut self, path: GodotString) -> bool {
+        self.sync();
+        let path = ProjectSettings::singleton().globalize_path(path);
+        match self.neat.save_checkpoint(path.to_string()) {
+            Ok(()) => true,
+            Err(e) => {
+                godot_error!("saving checkpoint failed: {e:#?}");
+                false
+            }
+        }
+    }
+
+    /// Resumes a run saved with `save_checkpoint`
+    #[func]
+    pub fn load_checkpoint(&mut self, path: GodotString) -> bool {
+        let path = ProjectSettings::singleton().globalize_path(path);
+        match self.neat.load_checkpoint(path.to_string()) {
+            Ok(()) => {
+                self.inputs = self.neat.inputs;
+                self.outputs = self.neat.outputs;
+                self.best_fitness = self
+                    .neat
+                    .statistics
+                    .generations()
+                    .iter()
+                    .map(|generation| generation.best_fitness)
+                    .reduce(f64::max);
+                self.configuration = Gd::new(self.neat.configuration.clone());
+                self.seed = self.neat.seed() as i64;
+                true
+            }
+            Err(e) => {
+                godot_error!("loading checkpoint failed: {e:#?}");
+                false
+            }
+        }
+    }
+
+    /// Emits the signals due after every evaluated generation
+    fn emit_progress(&mut self) {
+        let generation = self.neat.generation();
+        let stats = generation_dictionary(self.neat.statistics.last().unwrap());
+        self.base.emit_signal(
+            "generation_finished".into(),
+            &[generation.to_variant(), stats.to_variant()],
+        );
+
+        let (best_id, best_fitness) = self.neat.get_best();
+        if self
+            .best_fitness
+            .map_or(true, |fitness| best_fitness > fitness)
+        {
+            self.best_fitness = Some(best_fitness);
+            let genome = Gd::new(Genome {
+                genome: self.neat.genomes.get(best_id).unwrap().clone(),
+            });
+            self.base.emit_signal(
+                "new_best_genome".into(),
+                &[genome.to_variant(), best_fitness.to_variant()],
+            );
+        }
+
+        if self.neat.is_finished() {
+            let result = self.get_result();
+            self.base
+                .emit_signal("training_finished".into(), &[result.to_variant()]);
+        }
+    }
+
+    /// Hands the properties set from GDScript over to the core
+    fn sync(&mut self) {
+        self.neat.inputs = self.inputs;
+        self.neat.outputs = self.outputs;
+        self.neat.configuration = self.configuration.bind().clone();
+        // Setting the seed restarts the generator, which only an edited seed should do
+        if self.neat.seed() != self.seed as u64 {
+            self.neat.set_seed(self.seed as u64);
+        }
+    }
+}
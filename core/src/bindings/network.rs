@@ -0,0 +1,134 @@
+use godot::prelude::*;
+
+use super::Pack;
+use crate::{Connection, EnumConversion, NeuralNode};
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct Network {
+    pub network: crate::Network,
+}
+
+#[godot_api]
+impl RefCountedVirtual for Network {
+    fn init(_base: Base<RefCounted>) -> Self {
+        Network {
+            network: crate::Network::default(),
+        }
+    }
+}
+
+#[godot_api]
+impl Network {
+    #[func]
+    fn get_input_count(&self) -> u32 {
+        self.network.input_count
+    }
+
+    #[func]
+    fn get_output_count(&self) -> u32 {
+        self.network.output_count
+    }
+
+    /// look if it was a proper graph then this would be hard
+    #[func]
+    fn get_nodes(&self) -> VariantArray {
+        let mut nodes: Array<Gd<NeuralNode>> = Array::new();
+        let mut connections: Array<Gd<Connection>> = Array::new();
+        for node in &self.network.nodes {
+            nodes.push(Gd::new(node.clone()))
+        }
+        for connection in &self.network.connections {
+            connections.push(Gd::new(connection.clone()))
+        }
+        varray![nodes, connections]
+    }
+
+    /// Substrate coordinates of every node, empty unless the network was painted by HyperNEAT
+    #[func]
+    fn get_node_positions(&self) -> Array<PackedFloat64Array> {
+        let mut positions = Array::new();
+        for position in &self.network.node_positions {
+            positions.push(position.clone().pack());
+        }
+        positions
+    }
+
+    /// Substrate coordinates of the hidden nodes, to visualize where ES-HyperNEAT placed them
+    #[func]
+    fn get_hidden_positions(&self) -> Array<PackedFloat64Array> {
+        let mut positions = Array::new();
+        for position in self.network.hidden_positions() {
+            positions.push(position.to_vec().pack());
+        }
+        positions
+    }
+
+    #[func]
+    pub fn to_bytes(&self) -> PackedByteArray {
+        self.network.to_bytes().pack()
+    }
+
+    #[func]
+    pub fn from_bytes(bytes: PackedByteArray) -> Variant {
+        match crate::Network::from_bytes(&bytes.to_vec()) {
+            Ok(network) => Gd::new(Network { network }).to_variant(),
+            Err(e) => {
+                godot_error!("deserializing failed: {e:#?}");
+                Variant::nil()
+            }
+        }
+    }
+
+    #[func]
+    pub fn is_node_ready(&self, index: u32) -> bool {
+        self.network.is_node_ready(index)
+    }
+
+    #[func]
+    pub fn forward_pass(&mut self, inputs: PackedFloat64Array) -> PackedFloat64Array {
+        self.network.forward_pass(&inputs.to_vec()).pack() // note that result can be f32::NAN
+    }
+
+    #[func]
+    pub fn clear_values(&mut self) {
+        self.network.clear_values();
+    }
+
+    /// Forgets the node values kept between passes by recurrent connections
+    #[func]
+    pub fn reset_state(&mut self) {
+        self.network.reset_state();
+    }
+}
+
+#[godot_api]
+impl Connection {}
+
+#[godot_api]
+impl NeuralNode {
+    #[func]
+    fn get_kind(&self) -> u8 {
+        self.kind.clone().to()
+    }
+    #[func]
+    fn get_aggregation(&self) -> u8 {
+        self.aggregation.clone().to()
+    }
+    #[func]
+    fn get_activation(&self) -> u8 {
+        self.activation.clone().to()
+    }
+    #[func]
+    fn get_bias(&self) -> f64 {
+        self.bias
+    }
+    /// null till i can get signals
+    #[func]
+    fn get_value(&self) -> Variant {
+        if self.value.is_none() {
+            return Variant::nil();
+        }
+        self.value.unwrap().to_variant()
+    }
+}
@@ -0,0 +1,45 @@
+use godot::prelude::*;
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct Species {
+    pub species: crate::Species,
+}
+
+#[godot_api]
+impl RefCountedVirtual for Species {
+    fn to_string(&self) -> GodotString {
+        GodotString::from(format!("{:?}", self.species))
+    }
+}
+
+#[godot_api]
+impl Species {
+    #[func]
+    pub fn get_representative(&self) -> i64 {
+        self.species.representative().into()
+    }
+
+    #[func]
+    pub fn get_members(&self) -> PackedInt64Array {
+        let mut members = PackedInt64Array::new();
+        for member in &self.species.members {
+            members.push((*member).into())
+        }
+        members
+    }
+
+    /// The mean fitness of the members, null before the first speciation
+    #[func]
+    pub fn get_fitness(&self) -> Variant {
+        match self.species.fitness() {
+            Some(fitness) => fitness.to_variant(),
+            None => Variant::nil(),
+        }
+    }
+
+    #[func]
+    pub fn get_last_improved(&self) -> u32 {
+        self.species.last_improved()
+    }
+}
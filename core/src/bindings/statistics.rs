@@ -0,0 +1,83 @@
+use godot::engine::ProjectSettings;
+use godot::prelude::*;
+
+use crate::GenerationStatistics;
+
+/// A dictionary with the same keys as the fields of `GenerationStatistics`
+pub fn generation_dictionary(generation: &GenerationStatistics) -> Dictionary {
+    let mut species_sizes = PackedInt64Array::new();
+    for size in &generation.species_sizes {
+        species_sizes.push(*size as i64);
+    }
+
+    let mut dictionary = Dictionary::new();
+    dictionary.insert("generation", generation.generation);
+    dictionary.insert("best_fitness", generation.best_fitness);
+    dictionary.insert("mean_fitness", generation.mean_fitness);
+    dictionary.insert("median_fitness", generation.median_fitness);
+    dictionary.insert("stddev_fitness", generation.stddev_fitness);
+    dictionary.insert("species_count", generation.species_count);
+    dictionary.insert("species_sizes", species_sizes);
+    dictionary.insert(
+        "compatibility_threshold",
+        generation.compatibility_threshold,
+    );
+    dictionary.insert("mean_nodes", generation.mean_nodes);
+    dictionary.insert("mean_connections", generation.mean_connections);
+    dictionary.insert(
+        "stagnated_species_removed",
+        generation.stagnated_species_removed,
+    );
+    dictionary.insert("evaluation_time", generation.evaluation_time);
+    dictionary
+}
+
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct Statistics {
+    pub statistics: crate::Statistics,
+}
+
+#[godot_api]
+impl Statistics {
+    /// One dictionary per generation
+    #[func]
+    pub fn get_generations(&self) -> Array<Dictionary> {
+        let mut generations = Array::new();
+        for generation in self.statistics.generations() {
+            generations.push(generation_dictionary(generation));
+        }
+        generations
+    }
+
+    #[func]
+    pub fn to_csv(&self) -> GodotString {
+        self.statistics.to_csv().into()
+    }
+
+    #[func]
+    pub fn to_json(&self) -> GodotString {
+        self.statistics.to_json().into()
+    }
+
+    #[func]
+    pub fn save_csv(&self, path: GodotString) -> bool {
+        save(path, self.statistics.to_csv())
+    }
+
+    #[func]
+    pub fn save_json(&self, path: GodotString) -> bool {
+        save(path, self.statistics.to_json())
+    }
+}
+
+fn save(path: GodotString, contents: String) -> bool {
+    let path = ProjectSettings::singleton().globalize_path(path);
+    match std::fs::write(path.to_string(), contents) {
+        Ok(()) => true,
+        Err(e) => {
+            godot_error!("saving statistics failed: {e:#?}");
+            false
+        }
+    }
+}
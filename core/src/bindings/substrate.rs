@@ -0,0 +1,46 @@
+use godot::prelude::*;
+
+/// Node coordinates of the network HyperNEAT paints, every node needs the same number of them
+#[derive(GodotClass)]
+#[class(base=RefCounted)]
+pub struct Substrate {
+    pub substrate: crate::Substrate,
+}
+
+#[godot_api]
+impl RefCountedVirtual for Substrate {
+    fn init(_base: Base<RefCounted>) -> Self {
+        Substrate {
+            substrate: crate::Substrate::default(),
+        }
+    }
+}
+
+#[godot_api]
+impl Substrate {
+    #[func]
+    pub fn add_input(&mut self, position: PackedFloat64Array) {
+        self.substrate.inputs.push(position.to_vec());
+    }
+
+    /// Adds a node to the hidden layer with the given index, creating the layers up to it
+    #[func]
+    pub fn add_hidden(&mut self, layer: u32, position: PackedFloat64Array) {
+        let layers = &mut self.substrate.hidden_layers;
+        if layers.len() <= layer as usize {
+            layers.resize(layer as usize + 1, vec![]);
+        }
+        layers[layer as usize].push(position.to_vec());
+    }
+
+    #[func]
+    pub fn add_output(&mut self, position: PackedFloat64Array) {
+        self.substrate.outputs.push(position.to_vec());
+    }
+
+    /// How many coordinates every node has
+    #[func]
+    pub fn get_dimensions(&self) -> u32 {
+        self.substrate.dimensions() as u32
+    }
+}
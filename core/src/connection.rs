@@ -1,27 +1,14 @@
-use crate::genome::connection::ConnectionGene;
+#[cfg(feature = "godot")]
 use godot::prelude::*;
 use nanoserde::{DeBin, SerBin};
 
-#[derive(Debug, DeBin, SerBin, GodotClass, Clone)]
-#[class(base=RefCounted)]
+#[derive(Debug, DeBin, SerBin, Clone)]
+#[cfg_attr(feature = "godot", derive(GodotClass), class(base=RefCounted))]
 pub struct Connection {
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub from: u32,
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub to: u32,
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub weight: f64,
 }
-
-#[godot_api]
-impl Connection {}
-
-impl From<&ConnectionGene> for Connection {
-    fn from(g: &ConnectionGene) -> Self {
-        Connection {
-            from: g.from,
-            to: g.to,
-            weight: g.weight,
-        }
-    }
-}
@@ -1,32 +1,25 @@
-use godot::prelude::utilities::randf;
+use crate::random::Rng;
+use nanoserde::{DeBin, SerBin};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, DeBin, SerBin)]
 pub struct ConnectionGene {
     pub from: u32,
     pub to: u32,
     pub weight: f64,
     pub disabled: bool,
+    pub innovation: u32,
 }
 
 impl ConnectionGene {
-    pub fn new(from: u32, to: u32) -> Self {
+    pub fn new(from: u32, to: u32, innovation: u32, rng: &mut Rng) -> Self {
         ConnectionGene {
             from,
             to,
-            weight: randf() * 2.0 - 1.0,
+            weight: rng.randf() * 2.0 - 1.0,
             disabled: false,
+            innovation,
         }
     }
-
-    pub fn innovation_number(&self) -> u32 {
-        let a = self.from;
-        let b = self.to;
-
-        let first_part = (a + b) * (a + b + 1);
-        let second_part = b;
-
-        first_part.checked_div(2).unwrap() + second_part
-    }
 }
 
 impl PartialEq for ConnectionGene {
@@ -1,8 +1,10 @@
-use godot::prelude::utilities::randf;
+use crate::random::Rng;
+use std::collections::{HashMap, HashSet};
 
 use super::{ConnectionGene, Genome, NodeGene};
+use crate::node::NodeKind;
 
-pub fn crossover(a: (&Genome, f64), b: (&Genome, f64)) -> Option<Genome> {
+pub fn crossover(a: (&Genome, f64), b: (&Genome, f64), rng: &mut Rng) -> Option<Genome> {
     if (a.0.inputs != b.0.inputs) || (a.0.outputs != b.0.outputs) {
         return None;
     }
@@ -19,7 +21,8 @@ pub fn crossover(a: (&Genome, f64), b: (&Genome, f64)) -> Option<Genome> {
         std::mem::swap(&mut fitness_a, &mut fitness_b);
     }
 
-    let mut child = Genome::empty(parent_a.inputs, parent_a.outputs);
+    let mut child = Genome::empty(parent_a.inputs, parent_a.outputs, rng);
+    child.recurrent = parent_a.recurrent;
 
     let child_connection_genes: Vec<ConnectionGene> = parent_a
         .connection_genes
@@ -28,12 +31,12 @@ pub fn crossover(a: (&Genome, f64), b: (&Genome, f64)) -> Option<Genome> {
             let maybe_counterpart_connection = parent_b
                 .connection_genes
                 .iter()
-                .find(|cb| cb.innovation_number() == connection.innovation_number());
+                .find(|cb| cb.innovation == connection.innovation);
 
             // Chooses connection from one of the parents
             let chosen_connection =
                 if let Some(counterpart_connection) = maybe_counterpart_connection {
-                    if randf() < 0.5 {
+                    if rng.randf() < 0.5 {
                         connection
                     } else {
                         counterpart_connection
@@ -50,9 +53,9 @@ pub fn crossover(a: (&Genome, f64), b: (&Genome, f64)) -> Option<Genome> {
              */
             let new_disabled = if let Some(counterpart_connection) = maybe_counterpart_connection {
                 match (connection.disabled, counterpart_connection.disabled) {
-                    (true, true) => randf() < 0.75,
+                    (true, true) => rng.randf() < 0.75,
                     (false, false) => false,
-                    _ => randf() < 0.5,
+                    _ => rng.randf() < 0.5,
                 }
             } else {
                 connection.disabled
@@ -65,30 +68,167 @@ pub fn crossover(a: (&Genome, f64), b: (&Genome, f64)) -> Option<Genome> {
         })
         .collect();
 
-    let required_node_count = 1 + child_connection_genes
+    // Nodes are lined up by their ids, the fitter parent decides which ones are inherited
+    let child_node_genes: Vec<NodeGene> = parent_a
+        .node_genes
         .iter()
-        .fold(0, |max, c| u32::max(u32::max(max, c.from), c.to));
-
-    let child_node_genes: Vec<NodeGene> = (0..required_node_count as usize)
-        .map(
-            |i| match (parent_a.node_genes.get(i), parent_b.node_genes.get(i)) {
-                (Some(a), Some(b)) => {
-                    if randf() < 0.5 {
-                        a
-                    } else {
-                        b
-                    }
+        .filter(|node| {
+            !matches!(node.kind, NodeKind::Hidden)
+                || child_connection_genes
+                    .iter()
+                    .any(|c| c.from == node.id || c.to == node.id)
+        })
+        .map(|node| match parent_b.node(node.id) {
+            Some(counterpart_node) => {
+                if rng.randf() < 0.5 {
+                    node
+                } else {
+                    counterpart_node
                 }
-                (Some(a), None) => a,
-                (None, Some(b)) => b,
-                _ => unreachable!(),
-            },
-        )
+            }
+            None => node,
+        })
         .cloned()
         .collect();
 
     child.connection_genes = child_connection_genes;
     child.node_genes = child_node_genes;
 
+    repair(&mut child, &parent_a);
+
     child.node_order().and(Some(child))
 }
+
+/// Fixes what mixing genes of two parents can break, so the child is rarely discarded
+///
+/// Every connection comes from the fitter parent or has the same endpoints there, so the nodes
+/// it needs are inherited already. Connections disabled in one parent can come back enabled
+/// though, those closing a cycle in a non recurrent child are disabled again.
+fn repair(child: &mut Genome, parent_a: &Genome) {
+    if child.recurrent {
+        return;
+    }
+
+    // Connections that were enabled in the fitter parent get to stay first
+    let was_enabled = |c: &ConnectionGene| {
+        parent_a
+            .connection_genes
+            .iter()
+            .any(|pc| pc.innovation == c.innovation && !pc.disabled)
+    };
+    let mut indexes: Vec<usize> = (0..child.connection_genes.len())
+        .filter(|i| !child.connection_genes[*i].disabled)
+        .collect();
+    indexes.sort_by_key(|i| !was_enabled(&child.connection_genes[*i]));
+
+    let mut accepted: HashMap<u32, Vec<u32>> = HashMap::new();
+    for i in indexes {
+        let (from, to) = (child.connection_genes[i].from, child.connection_genes[i].to);
+        if reaches(&accepted, to, from) {
+            child.connection_genes[i].disabled = true;
+        } else {
+            accepted.entry(from).or_default().push(to);
+        }
+    }
+}
+
+/// Whether `target` can be reached from `source` following the given edges
+fn reaches(edges: &HashMap<u32, Vec<u32>>, source: u32, target: u32) -> bool {
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![source];
+
+    while let Some(node) = to_visit.pop() {
+        if node == target {
+            return true;
+        }
+        if visited.insert(node) {
+            if let Some(next) = edges.get(&node) {
+                to_visit.extend(next);
+            }
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::InnovationTracker;
+
+    /// One input, one output and two hidden nodes in between, `2 -> 3` and `3 -> 2`
+    /// are enabled as asked
+    fn parent(forward: bool, backward: bool, recurrent: bool) -> Genome {
+        let (mut innovations, mut rng) = (InnovationTracker::new(), Rng::new(0));
+        let mut genome = Genome::new(1, 1, recurrent, &mut innovations, &mut rng);
+        genome.add_node(2, &mut rng);
+        genome.add_node(3, &mut rng);
+        for (from, to, enabled) in [
+            (0, 2, true),
+            (2, 3, forward),
+            (3, 2, backward),
+            (3, 1, true),
+        ] {
+            let mut connection =
+                ConnectionGene::new(from, to, innovations.connection(from, to), &mut rng);
+            connection.disabled = !enabled;
+            genome.connection_genes.push(connection);
+        }
+        genome
+    }
+
+    fn is_enabled(genome: &Genome, from: u32, to: u32) -> bool {
+        genome
+            .connections()
+            .iter()
+            .any(|c| c.from == from && c.to == to && !c.disabled)
+    }
+
+    #[test]
+    fn repair_breaks_cycles_keeping_the_fitter_parents_connections() {
+        let parent_a = parent(true, false, false);
+        let mut child = parent(true, true, false);
+        assert!(child.node_order().is_none());
+
+        repair(&mut child, &parent_a);
+        assert!(is_enabled(&child, 2, 3));
+        assert!(!is_enabled(&child, 3, 2));
+        assert!(child.node_order().is_some());
+    }
+
+    #[test]
+    fn cycle_checks_of_repaired_children_skip_disabled_back_edges() {
+        let parent_a = parent(true, false, false);
+        let mut child = parent(true, true, false);
+        repair(&mut child, &parent_a);
+
+        // The disabled back edge is not followed when looking for cycles
+        for genome in [&child, &parent_a] {
+            assert!(!genome.can_connect(3, 2));
+            assert!(!genome.can_connect(0, 3));
+        }
+    }
+
+    #[test]
+    fn repair_keeps_cycles_of_recurrent_children() {
+        let parent_a = parent(true, false, true);
+        let mut child = parent(true, true, true);
+
+        repair(&mut child, &parent_a);
+        assert!(is_enabled(&child, 2, 3));
+        assert!(is_enabled(&child, 3, 2));
+    }
+
+    #[test]
+    fn children_of_parents_with_opposite_connections_are_never_cyclic() {
+        let (parent_a, parent_b) = (parent(true, false, false), parent(false, true, false));
+        let mut rng = Rng::new(0);
+
+        for _ in 0..50 {
+            let child = crossover((&parent_a, 2.), (&parent_b, 1.), &mut rng).unwrap();
+            assert!(!(is_enabled(&child, 2, 3) && is_enabled(&child, 3, 2)));
+            assert_eq!(child.connections().len(), parent_a.connections().len());
+            assert_eq!(child.nodes().len(), parent_a.nodes().len());
+        }
+    }
+}
@@ -1,24 +1,12 @@
-use godot::prelude::utilities::randi;
-use godot::prelude::*;
+use crate::random::Rng;
+use nanoserde::{DeBin, DeBinErr, SerBin};
 use std::fmt::Debug;
 
-#[derive(GodotClass, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
-#[class(base=RefCounted)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct GenomeId {
     uuid: [u8; 8],
 }
 
-#[godot_api]
-impl RefCountedVirtual for GenomeId {
-    fn init(_base: Base<Self::Base>) -> Self {
-        Self::default()
-    }
-
-    fn to_string(&self) -> GodotString {
-        format!("{self:?}").into()
-    }
-}
-
 impl Debug for GenomeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "GID<{}>", self.uuid_str())
@@ -26,32 +14,32 @@ impl Debug for GenomeId {
 }
 
 macro_rules! rand_u8 {
-    () => {
-        (randi() & 255) as u8
+    ($rng:expr) => {
+        ($rng.randi() & 255) as u8
     };
 }
 
 /// dont look at it too hard
-fn uuid_bin() -> [u8; 8] {
+fn uuid_bin(rng: &mut Rng) -> [u8; 8] {
     [
-        rand_u8!(),
-        rand_u8!(),
-        rand_u8!(),
-        rand_u8!(),
-        rand_u8!(),
-        rand_u8!(),
-        rand_u8!(),
-        rand_u8!(),
+        rand_u8!(rng),
+        rand_u8!(rng),
+        rand_u8!(rng),
+        rand_u8!(rng),
+        rand_u8!(rng),
+        rand_u8!(rng),
+        rand_u8!(rng),
+        rand_u8!(rng),
     ]
 }
 
-impl Default for GenomeId {
-    fn default() -> Self {
-        Self { uuid: uuid_bin() }
+impl GenomeId {
+    pub fn new(rng: &mut Rng) -> Self {
+        Self {
+            uuid: uuid_bin(rng),
+        }
     }
-}
 
-impl GenomeId {
     pub fn uuid_str(&self) -> String {
         format!("{:x}", ((self.uuid[0] as i32) << 8) + self.uuid[1] as i32)
     }
@@ -74,14 +62,14 @@ impl From<i64> for GenomeId {
     }
 }
 
-impl ToVariant for GenomeId {
-    fn to_variant(&self) -> Variant {
-        i64::from(*self).to_variant()
+impl SerBin for GenomeId {
+    fn ser_bin(&self, output: &mut Vec<u8>) {
+        i64::from(*self).ser_bin(output)
     }
 }
 
-impl FromVariant for GenomeId {
-    fn try_from_variant(variant: &Variant) -> Result<Self, VariantConversionError> {
-        Ok(i64::from_variant(variant).into())
+impl DeBin for GenomeId {
+    fn de_bin(offset: &mut usize, bytes: &[u8]) -> Result<Self, DeBinErr> {
+        Ok(i64::de_bin(offset, bytes)?.into())
     }
 }
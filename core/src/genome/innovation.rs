@@ -0,0 +1,118 @@
+use nanoserde::{DeBin, SerBin};
+use std::collections::HashMap;
+
+/// Hands out historical markings for structural mutations
+///
+/// Identical mutations that happen during the same generation receive the
+/// same innovation number, so homologous genes can be lined up later on.
+#[derive(Debug, Default, Clone, DeBin, SerBin)]
+pub struct InnovationTracker {
+    next_innovation: u32,
+    next_node: u32,
+    connections: HashMap<(u32, u32), u32>,
+    splits: HashMap<u32, u32>,
+}
+
+impl InnovationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next(&mut self) -> u32 {
+        let innovation = self.next_innovation;
+        self.next_innovation += 1;
+        innovation
+    }
+
+    /// Innovation number of a new connection between `from` and `to`
+    pub fn connection(&mut self, from: u32, to: u32) -> u32 {
+        if let Some(innovation) = self.connections.get(&(from, to)) {
+            return *innovation;
+        }
+
+        let innovation = self.next();
+        self.connections.insert((from, to), innovation);
+        innovation
+    }
+
+    /// Makes sure ids below `count` are never handed out to hidden nodes
+    pub fn reserve_nodes(&mut self, count: u32) {
+        self.next_node = u32::max(self.next_node, count);
+    }
+
+    /// A node id that was never handed out before
+    pub fn node(&mut self) -> u32 {
+        let id = self.next_node;
+        self.next_node += 1;
+        id
+    }
+
+    /// Id of the hidden node created by splitting the connection with the given innovation number
+    pub fn split(&mut self, innovation: u32) -> u32 {
+        if let Some(node) = self.splits.get(&innovation) {
+            return *node;
+        }
+
+        let node = self.node();
+        self.splits.insert(innovation, node);
+        node
+    }
+
+    /// Forgets the mutations of the current generation
+    pub fn next_generation(&mut self) {
+        self.connections.clear();
+        self.splits.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_connection_in_a_generation_gets_the_same_innovation() {
+        let mut innovations = InnovationTracker::new();
+        let first = innovations.connection(0, 2);
+        let second = innovations.connection(1, 2);
+
+        assert_ne!(first, second);
+        assert_eq!(innovations.connection(0, 2), first);
+        assert_eq!(innovations.connection(1, 2), second);
+    }
+
+    #[test]
+    fn same_split_in_a_generation_gets_the_same_node() {
+        let mut innovations = InnovationTracker::new();
+        innovations.reserve_nodes(3);
+        let node = innovations.split(0);
+
+        assert_eq!(node, 3);
+        assert_eq!(innovations.split(0), node);
+        assert_eq!(innovations.split(1), 4);
+        assert_eq!(innovations.node(), 5);
+    }
+
+    #[test]
+    fn next_generation_hands_out_new_numbers() {
+        let mut innovations = InnovationTracker::new();
+        innovations.reserve_nodes(3);
+        let connection = innovations.connection(0, 2);
+        let node = innovations.split(connection);
+
+        innovations.next_generation();
+        let next_connection = innovations.connection(0, 2);
+        assert!(next_connection > connection);
+        assert!(innovations.split(connection) > node);
+        // Innovations of the new generation are shared again
+        assert_eq!(innovations.connection(0, 2), next_connection);
+    }
+
+    #[test]
+    fn reserved_nodes_are_never_handed_out() {
+        let mut innovations = InnovationTracker::new();
+        innovations.reserve_nodes(5);
+        innovations.reserve_nodes(2);
+
+        assert_eq!(innovations.node(), 5);
+    }
+}
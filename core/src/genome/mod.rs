@@ -1,31 +1,39 @@
-use godot::prelude::*;
-use std::collections::VecDeque;
+use nanoserde::{DeBin, DeBinErr, SerBin};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 
 use crate::mutations::MutationKind;
+use crate::network::Network;
 use crate::node::NodeKind;
-use crate::Map;
+use crate::random::Rng;
 pub use connection::ConnectionGene;
 pub use crossover::*;
 pub use gid::GenomeId;
+pub use innovation::InnovationTracker;
 pub use node::NodeGene;
 
-pub type GenomeMap = Map<GenomeId, Genome>;
+pub type GenomeMap = BTreeMap<GenomeId, Genome>;
 
 pub mod connection;
 pub mod crossover;
 pub mod gid;
+pub mod innovation;
 pub mod node;
 
-#[derive(Clone, PartialEq, GodotClass)]
-#[class(base=RefCounted)]
+#[derive(Clone, PartialEq, DeBin, SerBin)]
 pub struct Genome {
     id: GenomeId,
-    #[export(get, set)]
     inputs: u32,
-    #[export(get, set)]
     outputs: u32,
+    /// Whether recurrent and self-loop connections are allowed
+    recurrent: bool,
     pub fitness: Option<f64>,
+    /// What selection ranks the genome by when it differs from the fitness, as in novelty search
+    pub score: Option<f64>,
+    /// Characterizes what the network did during its last evaluation, used by novelty search
+    pub behavior: Vec<f64>,
+    /// The objectives of the last evaluation, used by multi-objective optimization
+    pub objectives: Vec<f64>,
     connection_genes: Vec<ConnectionGene>,
     node_genes: Vec<NodeGene>,
 }
@@ -36,55 +44,234 @@ impl Debug for Genome {
     }
 }
 
-#[godot_api]
-impl RefCountedVirtual for Genome {
-    fn to_string(&self) -> GodotString {
-        format!("{self:?}").into()
-    }
-}
-
-#[godot_api]
 impl Genome {
-    #[func]
     pub fn fitness(&self) -> f64 {
         self.fitness.unwrap_or(f64::NEG_INFINITY)
     }
 
-    pub fn new(inputs: u32, outputs: u32) -> Self {
-        let mut node_genes = vec![];
+    /// What selection ranks the genome by, the fitness unless something else was scored
+    pub fn score(&self) -> f64 {
+        self.score.unwrap_or_else(|| self.fitness())
+    }
 
-        (0..inputs).for_each(|_| node_genes.push(NodeGene::new(NodeKind::Input)));
-        (0..outputs).for_each(|_| node_genes.push(NodeGene::new(NodeKind::Output)));
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.serialize_bin()
+    }
 
-        let connection_genes: Vec<ConnectionGene> = (0..inputs)
-            .flat_map(|i| {
-                (inputs..inputs + outputs)
-                    .map(|o| ConnectionGene::new(i, o))
-                    .collect::<Vec<ConnectionGene>>()
-            })
-            .collect();
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeBinErr> {
+        Self::deserialize_bin(bytes)
+    }
+
+    /// Human readable listing of every gene, disabled connections included
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "genome {} inputs {} outputs {} recurrent {} fitness {:?}\n",
+            self.id.full_str(),
+            self.inputs,
+            self.outputs,
+            self.recurrent,
+            self.fitness
+        );
+
+        for node in &self.node_genes {
+            text += &format!(
+                "node {} {:?} activation {:?} aggregation {:?} bias {}\n",
+                node.id, node.kind, node.activation, node.aggregation, node.bias
+            );
+        }
+
+        for connection in &self.connection_genes {
+            text += &format!(
+                "connection {} -> {} weight {} innovation {}{}\n",
+                connection.from,
+                connection.to,
+                connection.weight,
+                connection.innovation,
+                if connection.disabled { " disabled" } else { "" }
+            );
+        }
+
+        text
+    }
+
+    /// Every input connected to every output
+    pub fn new(
+        inputs: u32,
+        outputs: u32,
+        recurrent: bool,
+        innovations: &mut InnovationTracker,
+        rng: &mut Rng,
+    ) -> Self {
+        let mut genome = Genome::unconnected(inputs, outputs, recurrent, innovations, rng);
+
+        for i in 0..inputs {
+            for o in inputs..inputs + outputs {
+                genome.connection_genes.push(ConnectionGene::new(
+                    i,
+                    o,
+                    innovations.connection(i, o),
+                    rng,
+                ));
+            }
+        }
+
+        genome
+    }
+
+    /// Input and output nodes without any connections between them
+    pub fn unconnected(
+        inputs: u32,
+        outputs: u32,
+        recurrent: bool,
+        innovations: &mut InnovationTracker,
+        rng: &mut Rng,
+    ) -> Self {
+        let mut node_genes = vec![];
+
+        // Input and output nodes have the same ids in every genome, the id after them is
+        // kept for a bias node
+        innovations.reserve_nodes(inputs + outputs + 1);
+        (0..inputs).for_each(|i| node_genes.push(NodeGene::new(NodeKind::Input, i, rng)));
+        (inputs..inputs + outputs)
+            .for_each(|o| node_genes.push(NodeGene::new(NodeKind::Output, o, rng)));
 
         Genome {
-            id: GenomeId::default(),
+            id: GenomeId::new(rng),
             fitness: None,
+            score: None,
+            behavior: vec![],
+            objectives: vec![],
             inputs,
             outputs,
-            connection_genes,
+            recurrent,
+            connection_genes: vec![],
             node_genes,
         }
     }
 
-    pub fn empty(inputs: u32, outputs: u32) -> Self {
+    pub fn empty(inputs: u32, outputs: u32, rng: &mut Rng) -> Self {
         Genome {
-            id: GenomeId::default(),
+            id: GenomeId::new(rng),
             inputs,
             outputs,
+            recurrent: false,
             fitness: None,
+            score: None,
+            behavior: vec![],
+            objectives: vec![],
             connection_genes: vec![],
             node_genes: vec![],
         }
     }
 
+    /// Rebuilds the genome of a trained network, hidden nodes get fresh ids
+    pub fn from_network(
+        network: &Network,
+        recurrent: bool,
+        innovations: &mut InnovationTracker,
+        rng: &mut Rng,
+    ) -> Self {
+        let inputs = network.input_count;
+        let outputs = network.output_count;
+        innovations.reserve_nodes(inputs + outputs + 1);
+
+        let mut next_input = 0;
+        let mut next_output = inputs;
+        let node_ids: Vec<u32> = network
+            .nodes
+            .iter()
+            .map(|node| match node.kind {
+                NodeKind::Input => {
+                    next_input += 1;
+                    next_input - 1
+                }
+                NodeKind::Output => {
+                    next_output += 1;
+                    next_output - 1
+                }
+                NodeKind::Constant => inputs + outputs,
+                _ => innovations.node(),
+            })
+            .collect();
+
+        let node_genes: Vec<NodeGene> = network
+            .nodes
+            .iter()
+            .zip(&node_ids)
+            .map(|(node, id)| NodeGene {
+                id: *id,
+                kind: node.kind.clone(),
+                aggregation: node.aggregation.clone(),
+                activation: node.activation.clone(),
+                bias: node.bias,
+            })
+            .collect();
+
+        let connection_genes: Vec<ConnectionGene> = network
+            .connections
+            .iter()
+            .map(|connection| {
+                let from = node_ids[connection.from as usize];
+                let to = node_ids[connection.to as usize];
+                ConnectionGene {
+                    from,
+                    to,
+                    weight: connection.weight,
+                    disabled: false,
+                    innovation: innovations.connection(from, to),
+                }
+            })
+            .collect();
+
+        Genome {
+            id: GenomeId::new(rng),
+            inputs,
+            outputs,
+            recurrent,
+            fitness: None,
+            score: None,
+            behavior: vec![],
+            objectives: vec![],
+            connection_genes,
+            node_genes,
+        }
+    }
+
+    /// Gives the genes of a genome coming from another run fresh hidden node ids
+    /// and innovation numbers from the given tracker
+    pub fn register(&mut self, innovations: &mut InnovationTracker) {
+        innovations.reserve_nodes(self.inputs + self.outputs + 1);
+
+        let new_ids: HashMap<u32, u32> = self
+            .node_genes
+            .iter()
+            .filter(|node| matches!(node.kind, NodeKind::Hidden))
+            .map(|node| (node.id, innovations.node()))
+            .collect();
+        let new_id = |id: u32| *new_ids.get(&id).unwrap_or(&id);
+
+        self.node_genes
+            .iter_mut()
+            .for_each(|node| node.id = new_id(node.id));
+        self.connection_genes.iter_mut().for_each(|connection| {
+            connection.from = new_id(connection.from);
+            connection.to = new_id(connection.to);
+            connection.innovation = innovations.connection(connection.from, connection.to);
+        });
+    }
+
+    /// A copy of the genome with its own id and no fitness
+    pub fn duplicate(&self, rng: &mut Rng) -> Self {
+        Genome {
+            id: GenomeId::new(rng),
+            fitness: None,
+            score: None,
+            behavior: vec![],
+            objectives: vec![],
+            ..self.clone()
+        }
+    }
+
     pub fn id(&self) -> GenomeId {
         self.id
     }
@@ -97,12 +284,24 @@ impl Genome {
         self.outputs
     }
 
+    pub fn is_recurrent(&self) -> bool {
+        self.recurrent
+    }
+
     pub fn nodes(&self) -> &[NodeGene] {
         &self.node_genes
     }
 
-    pub fn node_mut(&mut self, index: usize) -> Option<&mut NodeGene> {
-        self.node_genes.get_mut(index)
+    pub fn node(&self, id: u32) -> Option<&NodeGene> {
+        self.node_genes.iter().find(|n| n.id == id)
+    }
+
+    pub fn node_mut(&mut self, id: u32) -> Option<&mut NodeGene> {
+        self.node_genes.iter_mut().find(|n| n.id == id)
+    }
+
+    pub fn has_node(&self, id: u32) -> bool {
+        self.node(id).is_some()
     }
 
     pub fn connections(&self) -> &[ConnectionGene] {
@@ -128,40 +327,60 @@ impl Genome {
             connections.append(&mut conns);
         }
 
-        if connections.is_empty() {
-            return None;
-        }
-
         let mut visited: Vec<u32> = vec![];
 
-        // Input nodes are automatically visited as they get their values from inputs
+        // Input nodes are automatically visited as they get their values from inputs,
+        // the same goes for the bias node
         self.node_genes
             .iter()
-            .enumerate()
-            .filter(|(_, n)| matches!(n.kind, NodeKind::Input))
-            .for_each(|(i, _)| {
-                visited.push(i as u32);
+            .filter(|n| matches!(n.kind, NodeKind::Input | NodeKind::Constant))
+            .for_each(|n| {
+                visited.push(n.id);
             });
 
-        let mut newly_visited = 1;
-        while newly_visited != 0 {
-            newly_visited = 0;
-
-            let mut nodes_to_visit: Vec<u32> = (0..self.node_genes.len())
-                .map(|x| x as u32)
-                .filter(|i| {
-                    // The node is not visited but all prerequisite nodes are visited
-                    !visited.contains(i)
-                        && connections
-                            .iter()
-                            .filter(|c| c.to == *i)
-                            .map(|c| c.from)
-                            .all(|node_index| visited.contains(&node_index))
-                })
-                .collect();
+        loop {
+            let mut newly_visited = 1;
+            while newly_visited != 0 {
+                newly_visited = 0;
 
-            newly_visited += nodes_to_visit.len();
-            visited.append(&mut nodes_to_visit);
+                let mut nodes_to_visit: Vec<u32> = self
+                    .node_genes
+                    .iter()
+                    .map(|n| n.id)
+                    .filter(|i| {
+                        // The node is not visited but all prerequisite nodes are visited
+                        !visited.contains(i)
+                            && connections
+                                .iter()
+                                .filter(|c| c.to == *i)
+                                .map(|c| c.from)
+                                .all(|node_id| visited.contains(&node_id))
+                    })
+                    .collect();
+
+                newly_visited += nodes_to_visit.len();
+                visited.append(&mut nodes_to_visit);
+            }
+
+            if visited.len() == self.node_genes.len() || !self.recurrent {
+                break;
+            }
+
+            // Cycles are broken at the node missing the fewest prerequisites,
+            // its recurrent connections carry values from the previous pass
+            let forced_node = self
+                .node_genes
+                .iter()
+                .map(|n| n.id)
+                .filter(|i| !visited.contains(i))
+                .min_by_key(|i| {
+                    connections
+                        .iter()
+                        .filter(|c| c.to == *i && !visited.contains(&c.from))
+                        .count()
+                })
+                .unwrap();
+            visited.push(forced_node);
         }
 
         if visited.len() != self.node_genes.len() {
@@ -171,6 +390,7 @@ impl Genome {
         Some(visited)
     }
 
+    /// Node ids in the order they need to be calculated
     pub fn node_order(&self) -> Option<Vec<u32>> {
         self.calculate_node_order(None)
     }
@@ -179,15 +399,13 @@ impl Genome {
         self.calculate_node_order(Some(additional_connections))
     }
 
-    /// Dictionary<u32,u32>
-    fn calculate_node_distance_from_inputs(&self) -> Dictionary {
-        // Inputs are immediately added with distance of 0
-        let mut distances = Dictionary::new();
+    fn calculate_node_distance_from_inputs(&self) -> HashMap<u32, u32> {
+        // Inputs and the bias node are immediately added with distance of 0
+        let mut distances = HashMap::new();
         self.nodes()
             .iter()
-            .enumerate()
-            .filter(|(_, n)| matches!(n.kind, NodeKind::Input))
-            .map(|(i, _)| i as u32)
+            .filter(|n| matches!(n.kind, NodeKind::Input | NodeKind::Constant))
+            .map(|n| n.id)
             .for_each(|i| {
                 distances.insert(i, 0);
             });
@@ -196,36 +414,35 @@ impl Genome {
         let mut to_visit: VecDeque<u32> = self
             .nodes()
             .iter()
-            .enumerate()
-            .filter(|(_, n)| matches!(n.kind, NodeKind::Input))
-            .map(|(i, _)| i as u32)
+            .filter(|n| matches!(n.kind, NodeKind::Input | NodeKind::Constant))
+            .map(|n| n.id)
             .collect();
 
         while let Some(i) = to_visit.pop_front() {
-            let source_distance = u32::from_variant(&distances.get(i).unwrap_or(0.to_variant()));
+            let source_distance = *distances.get(&i).unwrap_or(&0);
 
+            // Disabled back edges would make the distances grow forever
             self.connections()
                 .iter()
-                .filter(|c| c.from == i)
+                .filter(|c| c.from == i && !c.disabled)
                 .for_each(|c| {
-                    let node_index = c.to;
+                    let node_id = c.to;
                     let potential_distance = source_distance + 1;
 
-                    let maybe_change = if let Some(distance) = distances.get(node_index) {
-                        let distance = u32::from_variant(&distance);
-                        if potential_distance > distance {
-                            to_visit.push_back(node_index);
+                    let maybe_change = if let Some(distance) = distances.get(&node_id) {
+                        if potential_distance > *distance {
+                            to_visit.push_back(node_id);
                             Some(potential_distance)
                         } else {
                             None
                         }
                     } else {
-                        to_visit.push_back(node_index);
+                        to_visit.push_back(node_id);
                         Some(potential_distance)
                     };
 
                     if let Some(new_distance) = maybe_change {
-                        distances.insert(node_index, new_distance);
+                        distances.insert(node_id, new_distance);
                     }
                 });
         }
@@ -245,21 +462,21 @@ impl Genome {
     // }
 
     fn is_projecting(&self, source: u32, target: u32) -> bool {
-        let mut visited_nodes = Dictionary::new();
+        let mut visited_nodes = HashSet::new();
         let mut nodes_to_visit: VecDeque<u32> = VecDeque::new();
 
         nodes_to_visit.push_back(source);
 
         let mut projecting = false;
         while let Some(i) = nodes_to_visit.pop_front() {
-            visited_nodes.insert(i, Variant::nil());
+            visited_nodes.insert(i);
             if self.is_projecting_directly(i, target) {
                 projecting = true;
                 break;
             } else {
                 self.connection_genes
                     .iter()
-                    .filter(|c| c.from == i && !c.disabled && !visited_nodes.contains_key(i))
+                    .filter(|c| c.from == i && !c.disabled && !visited_nodes.contains(&c.to))
                     .for_each(|c| nodes_to_visit.push_back(c.to));
             }
         }
@@ -272,25 +489,41 @@ impl Genome {
     // }
 
     pub fn can_connect(&self, from: u32, to: u32) -> bool {
-        let from_node = self.node_genes.get(from as usize).unwrap();
-        let to_node = self.node_genes.get(to as usize).unwrap();
+        let from_node = self.node(from).unwrap();
+        let to_node = self.node(to).unwrap();
 
         let is_from_output = matches!(from_node.kind, NodeKind::Output);
-        let is_to_input = matches!(to_node.kind, NodeKind::Input);
+        let is_to_input = matches!(to_node.kind, NodeKind::Input | NodeKind::Constant);
+
+        // Any node can feed back into any non input node, including itself
+        if self.recurrent {
+            return !is_to_input && !self.is_projecting_directly(from, to);
+        }
+
+        if is_from_output || is_to_input {
+            return false;
+        }
 
+        // Nodes the inputs don't reach yet, as in sparse initial topologies, have no distance
+        // so it can't tell whether the connection would close a cycle
         let distances = self.calculate_node_distance_from_inputs();
-        let from_distance = u32::from_variant(&distances.get(from).unwrap());
-        let to_distance = u32::from_variant(&distances.get(to).unwrap_or(u32::MAX.to_variant()));
+        let from_distance = *distances.get(&from).unwrap_or(&0);
+        let to_distance = *distances.get(&to).unwrap_or(&u32::MAX);
         let is_recurrent = from_distance > to_distance;
+        let is_unreached = !distances.contains_key(&from) || !distances.contains_key(&to);
 
-        if is_from_output || is_to_input || is_recurrent {
-            false
-        } else {
-            !self.is_projecting(from, to)
-        }
+        !is_recurrent
+            && !self.is_projecting(from, to)
+            && (!is_unreached || !self.is_projecting(to, from))
     }
 
-    pub fn add_connection(&mut self, from: u32, to: u32) -> Option<usize> {
+    pub fn add_connection(
+        &mut self,
+        from: u32,
+        to: u32,
+        innovation: u32,
+        rng: &mut Rng,
+    ) -> Option<usize> {
         if !self.can_connect(from, to) {
             return None;
         }
@@ -300,19 +533,24 @@ impl Genome {
             .iter_mut()
             .find(|c| c.from == from && c.to == to);
 
-        if let Some(mut conn) = maybe_connection {
+        if let Some(conn) = maybe_connection {
             conn.disabled = false;
         } else {
-            self.connection_genes.push(ConnectionGene::new(from, to));
+            self.connection_genes
+                .push(ConnectionGene::new(from, to, innovation, rng));
         }
 
         Some(self.connection_genes.len() - 1)
     }
 
-    pub fn add_many_connections(&mut self, params: &[(u32, u32)]) -> Vec<Option<usize>> {
+    pub fn add_many_connections(
+        &mut self,
+        params: &[(u32, u32, u32)],
+        rng: &mut Rng,
+    ) -> Vec<Option<usize>> {
         let results = params
             .iter()
-            .map(|(from, to)| self.add_connection(*from, *to))
+            .map(|(from, to, innovation)| self.add_connection(*from, *to, *innovation, rng))
             .collect();
 
         results
@@ -326,15 +564,205 @@ impl Genome {
         indexes.iter().for_each(|i| self.disable_connection(*i));
     }
 
-    /// Add a new hidden node to the genome
-    pub fn add_node(&mut self) -> usize {
-        let index = self.node_genes.len();
-        self.node_genes.push(NodeGene::new(NodeKind::Hidden));
+    /// The id of the bias node, if the genome has one
+    pub fn bias_node(&self) -> Option<u32> {
+        self.node_genes
+            .iter()
+            .find(|node| matches!(node.kind, NodeKind::Constant))
+            .map(|node| node.id)
+    }
+
+    /// Adds a bias node, which always outputs 1 and connects like an input,
+    /// it comes right after the outputs so it has the same id in every genome
+    pub fn add_bias_node(&mut self, innovations: &mut InnovationTracker, rng: &mut Rng) -> u32 {
+        if let Some(id) = self.bias_node() {
+            return id;
+        }
+
+        let id = self.inputs + self.outputs;
+        assert!(
+            !self.has_node(id),
+            "node {} is already taken, the bias node id has to be kept free",
+            id
+        );
+        innovations.reserve_nodes(id + 1);
+        self.node_genes
+            .push(NodeGene::new(NodeKind::Constant, id, rng));
+
+        id
+    }
+
+    /// Zeroes the bias of every node, for classic NEAT where only the bias node provides biases
+    pub fn clear_biases(&mut self) {
+        self.node_genes.iter_mut().for_each(|node| node.bias = 0.);
+    }
+
+    /// Add a new hidden node with the given id to the genome
+    pub fn add_node(&mut self, id: u32, rng: &mut Rng) -> u32 {
+        self.node_genes
+            .push(NodeGene::new(NodeKind::Hidden, id, rng));
+
+        id
+    }
+
+    pub fn mutate(
+        &mut self,
+        kind: &MutationKind,
+        innovations: &mut InnovationTracker,
+        rng: &mut Rng,
+    ) {
+        crate::mutations::mutate(kind, self, innovations, rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationKind;
+    use crate::aggregations::Aggregation;
+    use crate::connection::Connection;
+    use crate::node::NeuralNode;
+
+    fn node(kind: NodeKind) -> NeuralNode {
+        NeuralNode {
+            kind,
+            aggregation: Aggregation::Sum,
+            activation: ActivationKind::Identity,
+            bias: 0.,
+            value: None,
+        }
+    }
+
+    /// One input, one hidden node and one output, the hidden node would take the bias node id
+    fn genome_with_hidden_node(rng: &mut Rng) -> Genome {
+        let mut genome = Genome::unconnected(1, 1, false, &mut InnovationTracker::new(), rng);
+        genome.add_node(2, rng);
+        genome
+    }
+
+    fn assert_unique_ids(genome: &Genome) {
+        let ids: HashSet<u32> = genome.nodes().iter().map(|node| node.id).collect();
+        assert_eq!(ids.len(), genome.nodes().len());
+    }
+
+    /// One input, one output and two hidden nodes, `0 -> 2 -> 1` plus the given connections
+    fn hidden_genome(recurrent: bool, links: &[(u32, u32)]) -> Genome {
+        let (mut innovations, mut rng) = (InnovationTracker::new(), Rng::new(0));
+        let mut genome = Genome::unconnected(1, 1, recurrent, &mut innovations, &mut rng);
+        genome.add_node(2, &mut rng);
+        genome.add_node(3, &mut rng);
+        for (from, to) in [(0, 2), (2, 1)].iter().chain(links) {
+            let innovation = innovations.connection(*from, *to);
+            genome
+                .connection_genes
+                .push(ConnectionGene::new(*from, *to, innovation, &mut rng));
+        }
+        genome
+    }
+
+    #[test]
+    fn recurrent_genomes_can_connect_backwards_and_to_themselves() {
+        let recurrent = hidden_genome(true, &[]);
+        assert!(recurrent.can_connect(1, 2));
+        assert!(recurrent.can_connect(2, 2));
+        assert!(recurrent.can_connect(1, 1));
+        assert!(!recurrent.can_connect(2, 0));
+        assert!(!recurrent.can_connect(0, 2));
+
+        let feed_forward = hidden_genome(false, &[]);
+        assert!(!feed_forward.can_connect(1, 2));
+        assert!(!feed_forward.can_connect(2, 0));
+        assert!(feed_forward.can_connect(2, 3));
+    }
 
-        index
+    #[test]
+    fn cycles_are_ordered_only_in_recurrent_genomes() {
+        let cycles = [(2, 3), (3, 2), (1, 1)];
+
+        assert_eq!(
+            hidden_genome(true, &cycles).node_order(),
+            Some(vec![0, 2, 3, 1])
+        );
+        assert_eq!(hidden_genome(false, &cycles).node_order(), None);
+        assert_eq!(
+            hidden_genome(false, &[]).node_order(),
+            Some(vec![0, 2, 3, 1])
+        );
     }
 
-    pub fn mutate(&mut self, kind: &MutationKind) {
-        crate::mutations::mutate(kind, self);
+    #[test]
+    fn mutated_genomes_survive_serialization() {
+        let (mut innovations, mut rng) = (InnovationTracker::new(), Rng::new(0));
+        let mut genome = Genome::new(2, 1, false, &mut innovations, &mut rng);
+        for _ in 0..5 {
+            genome.mutate(&MutationKind::AddNode, &mut innovations, &mut rng);
+            genome.mutate(&MutationKind::AddConnection, &mut innovations, &mut rng);
+            genome.mutate(&MutationKind::ModifyWeight, &mut innovations, &mut rng);
+            genome.mutate(&MutationKind::ModifyActivation, &mut innovations, &mut rng);
+        }
+        genome.fitness = Some(0.5);
+
+        let loaded = Genome::from_bytes(&genome.to_bytes()).unwrap();
+        let innovation_numbers = |genome: &Genome| -> Vec<u32> {
+            genome.connections().iter().map(|c| c.innovation).collect()
+        };
+        assert!(genome.nodes().len() > 3);
+        assert_eq!(loaded.nodes(), genome.nodes());
+        assert_eq!(loaded.connections(), genome.connections());
+        assert_eq!(innovation_numbers(&loaded), innovation_numbers(&genome));
+        assert_eq!(loaded.to_text(), genome.to_text());
+    }
+
+    #[test]
+    fn hidden_nodes_of_a_trained_network_leave_the_bias_node_id_free() {
+        let network = Network::feed_forward(
+            1,
+            1,
+            vec![
+                node(NodeKind::Input),
+                node(NodeKind::Hidden),
+                node(NodeKind::Output),
+            ],
+            vec![
+                Connection {
+                    from: 0,
+                    to: 1,
+                    weight: 1.,
+                },
+                Connection {
+                    from: 1,
+                    to: 2,
+                    weight: 1.,
+                },
+            ],
+        );
+        let mut innovations = InnovationTracker::new();
+        let mut rng = Rng::new(0);
+        let mut genome = Genome::from_network(&network, false, &mut innovations, &mut rng);
+
+        assert_eq!(genome.add_bias_node(&mut innovations, &mut rng), 2);
+        assert_unique_ids(&genome);
+    }
+
+    #[test]
+    fn registered_hidden_nodes_leave_the_bias_node_id_free() {
+        let mut innovations = InnovationTracker::new();
+        let mut rng = Rng::new(0);
+        let mut genome = genome_with_hidden_node(&mut rng);
+        genome.register(&mut innovations);
+
+        assert_eq!(genome.add_bias_node(&mut innovations, &mut rng), 2);
+        assert_eq!(genome.bias_node(), Some(2));
+        assert_unique_ids(&genome);
+        assert!(innovations.node() > 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "already taken")]
+    fn bias_node_never_reuses_a_hidden_node_id() {
+        let mut rng = Rng::new(0);
+        let mut genome = genome_with_hidden_node(&mut rng);
+
+        genome.add_bias_node(&mut InnovationTracker::new(), &mut rng);
     }
 }
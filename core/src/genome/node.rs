@@ -1,52 +1,33 @@
 use crate::activation::ActivationKind;
 use crate::aggregations::Aggregation;
 use crate::mutations::rand;
-use crate::node::EnumConversion;
 use crate::node::NodeKind;
-use godot::prelude::utilities::randf;
-use godot::prelude::*;
+use crate::random::Rng;
+use nanoserde::{DeBin, SerBin};
 
-#[derive(Debug, Clone, GodotClass)]
+#[derive(Debug, Clone, DeBin, SerBin)]
 pub struct NodeGene {
+    pub id: u32,
     pub kind: NodeKind,
     pub aggregation: Aggregation,
     pub activation: ActivationKind,
     pub bias: f64,
 }
 
-#[godot_api]
 impl NodeGene {
-    #[func]
-    fn get_kind(&self) -> u8 {
-        self.kind.clone().to()
-    }
-    #[func]
-    fn get_aggregation(&self) -> u8 {
-        self.aggregation.clone().to()
-    }
-    #[func]
-    fn get_activation(&self) -> u8 {
-        self.activation.clone().to()
-    }
-    #[func]
-    fn get_bias(&self) -> f64 {
-        self.bias
-    }
-}
-
-impl NodeGene {
-    pub fn new(kind: NodeKind) -> Self {
+    pub fn new(kind: NodeKind, id: u32, rng: &mut Rng) -> Self {
         let activation = match kind {
-            NodeKind::Input => ActivationKind::Input,
-            _ => rand(),
+            NodeKind::Input | NodeKind::Constant => ActivationKind::Input,
+            _ => rand(rng),
         };
         let bias: f64 = match kind {
-            NodeKind::Input => 0.,
-            _ => randf() * 2.9 - 1.0,
+            NodeKind::Input | NodeKind::Constant => 0.,
+            _ => rng.randf() * 2.9 - 1.0,
         };
 
         NodeGene {
-            aggregation: rand(),
+            id,
+            aggregation: rand(rng),
             kind,
             activation,
             bias,
@@ -56,7 +37,8 @@ impl NodeGene {
 
 impl PartialEq for NodeGene {
     fn eq(&self, other: &Self) -> bool {
-        self.kind == other.kind
+        self.id == other.id
+            && self.kind == other.kind
             && self.aggregation == other.aggregation
             && self.activation == other.activation
             && (self.bias - other.bias).abs() < f64::EPSILON
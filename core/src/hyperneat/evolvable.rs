@@ -0,0 +1,392 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use super::substrate::substrate_node;
+use super::{express, Substrate};
+use crate::connection::Connection;
+use crate::genome::Genome;
+use crate::neat::Configuration;
+use crate::network::Network;
+use crate::node::NodeKind;
+
+/// A square of the quadtree covering the substrate, `weight` is the CPPN output at its center
+struct QuadPoint {
+    x: f64,
+    y: f64,
+    width: f64,
+    weight: f64,
+    children: Vec<QuadPoint>,
+}
+
+impl QuadPoint {
+    /// Weights of the squares at the bottom of the tree under this one
+    fn leaf_weights(&self, weights: &mut Vec<f64>) {
+        if self.children.is_empty() {
+            weights.push(self.weight);
+        }
+        for child in &self.children {
+            child.leaf_weights(weights);
+        }
+    }
+
+    fn variance(&self) -> f64 {
+        let mut weights = vec![];
+        self.leaf_weights(&mut weights);
+        if self.children.is_empty() || weights.is_empty() {
+            return 0.;
+        }
+
+        let mean = weights.iter().sum::<f64>() / weights.len() as f64;
+        weights.iter().map(|w| (w - mean) * (w - mean)).sum::<f64>() / weights.len() as f64
+    }
+}
+
+/// A connection found in the quadtree, between two substrate positions, with its expressed weight
+struct Discovered {
+    from: Vec<f64>,
+    to: Vec<f64>,
+    weight: f64,
+}
+
+/// Queries the CPPN around one node of a two dimensional substrate
+struct Explorer<'a> {
+    substrate: &'a Substrate,
+    cppn: Network,
+    configuration: &'a Configuration,
+}
+
+impl Explorer<'_> {
+    /// The CPPN output for a connection from or to `source`
+    fn weight(&mut self, source: &[f64], x: f64, y: f64, outgoing: bool) -> f64 {
+        let point = [x, y];
+        if outgoing {
+            self.substrate.query(&mut self.cppn, source, &point)
+        } else {
+            self.substrate.query(&mut self.cppn, &point, source)
+        }
+    }
+
+    /// Splits squares down to the initial depth, and further where the weights still vary
+    fn divide(&mut self, source: &[f64], outgoing: bool, point: &mut QuadPoint, level: u32) {
+        let half = point.width / 2.;
+        for (dx, dy) in [(-1., -1.), (1., -1.), (-1., 1.), (1., 1.)] {
+            let (x, y) = (point.x + dx * half, point.y + dy * half);
+            point.children.push(QuadPoint {
+                x,
+                y,
+                width: half,
+                weight: self.weight(source, x, y, outgoing),
+                children: vec![],
+            });
+        }
+
+        let (initial_depth, max_depth, division_threshold) = (
+            self.configuration.es_initial_depth,
+            self.configuration.es_max_depth,
+            self.configuration.es_division_threshold,
+        );
+        if level < initial_depth || (level < max_depth && point.variance() > division_threshold) {
+            for child in point.children.iter_mut() {
+                self.divide(source, outgoing, child, level + 1);
+            }
+        }
+    }
+
+    /// Walks down uniform enough squares and keeps the ones standing out from their
+    /// neighbors, which lie in a band of differing weights
+    fn extract(
+        &mut self,
+        source: &[f64],
+        outgoing: bool,
+        point: &QuadPoint,
+        discovered: &mut Vec<Discovered>,
+    ) {
+        let (variance_threshold, band_threshold) = (
+            self.configuration.es_variance_threshold,
+            self.configuration.es_band_threshold,
+        );
+
+        for child in &point.children {
+            if child.variance() >= variance_threshold {
+                self.extract(source, outgoing, child, discovered);
+                continue;
+            }
+
+            let width = child.width;
+            let mut difference = |dx: f64, dy: f64| {
+                (child.weight - self.weight(source, child.x + dx, child.y + dy, outgoing)).abs()
+            };
+            let (left, right) = (difference(-width, 0.), difference(width, 0.));
+            let (bottom, top) = (difference(0., -width), difference(0., width));
+            let band = f64::max(f64::min(left, right), f64::min(bottom, top));
+            if band.is_nan() || band <= band_threshold {
+                continue;
+            }
+            let weight = match express(
+                child.weight,
+                self.configuration.hyperneat_expression_threshold,
+                self.configuration.hyperneat_max_weight,
+            ) {
+                Some(weight) => weight,
+                None => continue,
+            };
+
+            let position = vec![child.x, child.y];
+            let (from, to) = if outgoing {
+                (source.to_vec(), position)
+            } else {
+                (position, source.to_vec())
+            };
+            discovered.push(Discovered { from, to, weight });
+        }
+    }
+
+    /// Connections the CPPN expresses from or to `source`
+    fn connections(&mut self, source: &[f64], outgoing: bool) -> Vec<Discovered> {
+        let mut root = QuadPoint {
+            x: 0.,
+            y: 0.,
+            width: 1.,
+            weight: 0.,
+            children: vec![],
+        };
+        self.divide(source, outgoing, &mut root, 1);
+
+        let mut discovered = vec![];
+        self.extract(source, outgoing, &root, &mut discovered);
+        discovered
+    }
+}
+
+/// Positions compared exactly, discovered positions come from the same arithmetic
+fn key(position: &[f64]) -> Vec<u64> {
+    position
+        .iter()
+        .map(|coordinate| coordinate.to_bits())
+        .collect()
+}
+
+impl Substrate {
+    /// Paints a network whose hidden nodes are discovered with ES-HyperNEAT, the hidden
+    /// layers of the substrate are ignored and only its two dimensional inputs and outputs are used
+    ///
+    /// Hidden nodes are placed where the CPPN pattern has the most information, starting from
+    /// the inputs and exploring `es_iteration_level` times from the hidden nodes found so far.
+    /// Hidden nodes not on a path from an input to an output are left out, as are connections
+    /// towards hidden nodes discovered earlier, so the network stays feed-forward.
+    pub fn build_evolvable(&self, cppn: &Genome, configuration: &Configuration) -> Network {
+        assert!(
+            self.dimensions() == 2,
+            "ES-HyperNEAT needs a two dimensional substrate"
+        );
+        let mut explorer = Explorer {
+            substrate: self,
+            cppn: Network::from_genome(cppn),
+            configuration,
+        };
+
+        let mut hidden: Vec<Vec<f64>> = vec![];
+        let mut hidden_indexes: HashMap<Vec<u64>, usize> = HashMap::new();
+        let fixed: HashSet<Vec<u64>> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|position| key(position))
+            .collect();
+        let mut discovered: Vec<Discovered> = vec![];
+
+        // Inputs to hidden nodes, then hidden nodes to newly found hidden nodes
+        let mut unexplored: Vec<Vec<f64>> = self.inputs.clone();
+        for _ in 0..=configuration.es_iteration_level {
+            let mut found = vec![];
+            for source in &unexplored {
+                for connection in explorer.connections(source, true) {
+                    let target = key(&connection.to);
+                    if fixed.contains(&target) {
+                        continue;
+                    }
+                    if let Entry::Vacant(entry) = hidden_indexes.entry(target) {
+                        entry.insert(hidden.len());
+                        hidden.push(connection.to.clone());
+                        found.push(connection.to.clone());
+                    }
+                    discovered.push(connection);
+                }
+            }
+            unexplored = found;
+        }
+
+        // Hidden nodes to outputs, only towards hidden nodes that already exist
+        for output in &self.outputs {
+            for connection in explorer.connections(output, false) {
+                if hidden_indexes.contains_key(&key(&connection.from)) {
+                    discovered.push(connection);
+                }
+            }
+        }
+
+        // Node indexes: inputs, hidden nodes in discovery order, outputs
+        let input_count = self.inputs.len();
+        let output_offset = input_count + hidden.len();
+        let input_indexes: HashMap<Vec<u64>, usize> = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, position)| (key(position), i))
+            .collect();
+        let output_indexes: HashMap<Vec<u64>, usize> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, position)| (key(position), output_offset + i))
+            .collect();
+        let index_of = |position: &[f64]| {
+            let position = key(position);
+            input_indexes
+                .get(&position)
+                .copied()
+                .or_else(|| hidden_indexes.get(&position).map(|i| input_count + i))
+                .or_else(|| output_indexes.get(&position).copied())
+        };
+
+        let mut edges: Vec<(usize, usize, f64)> = discovered
+            .iter()
+            .filter_map(|connection| {
+                let (from, to) = (index_of(&connection.from)?, index_of(&connection.to)?);
+                Some((from, to, connection.weight))
+            })
+            .filter(|(from, to, _)| from < to)
+            .collect();
+        edges.sort_by_key(|edge| (edge.0, edge.1));
+        edges.dedup_by(|a, b| (a.0, a.1) == (b.0, b.1));
+
+        // Keep hidden nodes reachable from an input that also reach an output
+        let node_count = output_offset + self.outputs.len();
+        let mut from_inputs = vec![false; node_count];
+        (0..input_count).for_each(|i| from_inputs[i] = true);
+        for (from, to, _) in &edges {
+            if from_inputs[*from] {
+                from_inputs[*to] = true;
+            }
+        }
+        let mut to_outputs = vec![false; node_count];
+        (output_offset..node_count).for_each(|i| to_outputs[i] = true);
+        for (from, to, _) in edges.iter().rev() {
+            if to_outputs[*to] {
+                to_outputs[*from] = true;
+            }
+        }
+        let kept: Vec<bool> = (0..node_count)
+            .map(|i| i < input_count || i >= output_offset || (from_inputs[i] && to_outputs[i]))
+            .collect();
+
+        let mut new_index = vec![0; node_count];
+        let mut nodes = vec![];
+        let mut node_positions = vec![];
+        let positions = self
+            .inputs
+            .iter()
+            .map(|position| (NodeKind::Input, position))
+            .chain(hidden.iter().map(|position| (NodeKind::Hidden, position)))
+            .chain(
+                self.outputs
+                    .iter()
+                    .map(|position| (NodeKind::Output, position)),
+            );
+        for (i, (kind, position)) in positions.enumerate() {
+            if kept[i] {
+                new_index[i] = nodes.len() as u32;
+                nodes.push(substrate_node(kind));
+                node_positions.push(position.clone());
+            }
+        }
+
+        let connections: Vec<Connection> = edges
+            .into_iter()
+            .filter(|(from, to, _)| kept[*from] && kept[*to])
+            .map(|(from, to, weight)| Connection {
+                from: new_index[from],
+                to: new_index[to],
+                weight,
+            })
+            .collect();
+
+        let mut network = Network::feed_forward(
+            input_count as u32,
+            self.outputs.len() as u32,
+            nodes,
+            connections,
+        );
+        network.node_positions = node_positions;
+        network
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::linear_cppn;
+    use super::*;
+    use crate::activation::ActivationKind;
+
+    fn substrate() -> Substrate {
+        Substrate::new(
+            vec![vec![-1., -1.], vec![1., -1.]],
+            vec![vec![vec![0., 0.]]],
+            vec![vec![0., 1.]],
+        )
+    }
+
+    #[test]
+    fn uniform_pattern_discovers_no_hidden_nodes() {
+        let cppn = linear_cppn(&[0., 0., 0., 0.], ActivationKind::Identity);
+        let network = substrate().build_evolvable(&cppn, &Configuration::default());
+
+        assert_eq!(network.nodes.len(), 3);
+        assert!(network.hidden_positions().is_empty());
+        assert!(network.connections.is_empty());
+    }
+
+    #[test]
+    fn varying_pattern_discovers_connected_hidden_nodes() {
+        // The weight grows with the x coordinates of both ends of the connection
+        let cppn = linear_cppn(&[1., 0., 1., 0.], ActivationKind::Identity);
+        let configuration = Configuration::default();
+        let network = substrate().build_evolvable(&cppn, &configuration);
+
+        let hidden = network.hidden_positions().len();
+        assert!(hidden > 0);
+        assert_eq!(network.nodes.len(), 3 + hidden);
+        assert_eq!(network.node_positions.len(), network.nodes.len());
+        for node in 2..2 + hidden as u32 {
+            assert!(network.connections.iter().any(|c| c.to == node));
+            assert!(network.connections.iter().any(|c| c.from == node));
+        }
+        for connection in &network.connections {
+            assert!(connection.from < connection.to);
+            assert!(connection.weight.abs() <= configuration.hyperneat_max_weight);
+        }
+    }
+
+    #[test]
+    fn only_expressed_connections_are_discovered() {
+        let varying = linear_cppn(&[1., 0., 1., 0.], ActivationKind::Identity);
+        let configuration = Configuration {
+            hyperneat_expression_threshold: 1.,
+            ..Configuration::default()
+        };
+        let network = substrate().build_evolvable(&varying, &configuration);
+        assert!(network.connections.is_empty());
+
+        let nan = linear_cppn(&[f64::NAN; 4], ActivationKind::Identity);
+        let network = substrate().build_evolvable(&nan, &Configuration::default());
+        assert!(network.connections.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "two dimensional substrate")]
+    fn one_dimensional_substrate_is_rejected() {
+        let substrate = Substrate::new(vec![vec![-1.], vec![1.]], vec![], vec![vec![0.]]);
+        let cppn = linear_cppn(&[1., 1.], ActivationKind::Identity);
+        substrate.build_evolvable(&cppn, &Configuration::default());
+    }
+}
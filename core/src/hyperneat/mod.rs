@@ -0,0 +1,68 @@
+//! HyperNEAT, evolved genomes are CPPNs painting the connection weights of a substrate
+
+pub use substrate::Substrate;
+
+mod evolvable;
+mod substrate;
+
+/// Turns a CPPN output into a connection weight, outputs closer to 0 than `threshold`
+/// express no connection and the rest are rescaled to `[-max_weight, max_weight]`
+fn express(output: f64, threshold: f64, max_weight: f64) -> Option<f64> {
+    let magnitude = output.abs().min(1.);
+    // NaN outputs express no connection either, clamping the magnitude would hide them
+    if magnitude <= threshold || output.is_nan() {
+        return None;
+    }
+
+    let scale = if threshold < 1. {
+        (magnitude - threshold) / (1. - threshold)
+    } else {
+        1.
+    };
+    Some(output.signum() * scale * max_weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationKind;
+    use crate::aggregations::Aggregation;
+    use crate::genome::{Genome, InnovationTracker};
+    use crate::random::Rng;
+
+    /// A CPPN without hidden nodes, its output is the activated weighted sum of its inputs
+    pub(super) fn linear_cppn(weights: &[f64], activation: ActivationKind) -> Genome {
+        let inputs = weights.len() as u32;
+        let mut cppn = Genome::new(
+            inputs,
+            1,
+            false,
+            &mut InnovationTracker::new(),
+            &mut Rng::new(0),
+        );
+
+        let output = cppn.node_mut(inputs).unwrap();
+        output.activation = activation;
+        output.aggregation = Aggregation::Sum;
+        output.bias = 0.;
+        for (i, weight) in weights.iter().enumerate() {
+            cppn.connection_mut(i).unwrap().weight = *weight;
+        }
+        cppn
+    }
+
+    #[test]
+    fn weak_outputs_express_no_connection() {
+        assert_eq!(express(0.1, 0.2, 3.), None);
+        assert_eq!(express(-0.2, 0.2, 3.), None);
+        assert_eq!(express(f64::NAN, 0.2, 3.), None);
+    }
+
+    #[test]
+    fn expressed_weights_are_rescaled() {
+        assert_eq!(express(0.75, 0.5, 3.), Some(1.5));
+        assert_eq!(express(-1., 0.5, 3.), Some(-3.));
+        assert_eq!(express(5., 0.5, 3.), Some(3.));
+        assert_eq!(express(0.5, 0., 2.), Some(1.));
+    }
+}
@@ -0,0 +1,192 @@
+use nanoserde::{DeBin, SerBin};
+
+use super::express;
+use crate::activation::ActivationKind;
+use crate::aggregations::Aggregation;
+use crate::connection::Connection;
+use crate::genome::Genome;
+use crate::network::Network;
+use crate::node::{NeuralNode, NodeKind};
+
+/// Where the nodes of the network painted by a CPPN are, every node has coordinates
+/// with the same number of dimensions
+///
+/// Connections go from every input to every node of the first hidden layer, from every layer
+/// to the next one, and from the last layer to every output.
+#[derive(Debug, Clone, Default, PartialEq, DeBin, SerBin)]
+pub struct Substrate {
+    pub inputs: Vec<Vec<f64>>,
+    pub hidden_layers: Vec<Vec<Vec<f64>>>,
+    pub outputs: Vec<Vec<f64>>,
+}
+
+impl Substrate {
+    pub fn new(
+        inputs: Vec<Vec<f64>>,
+        hidden_layers: Vec<Vec<Vec<f64>>>,
+        outputs: Vec<Vec<f64>>,
+    ) -> Self {
+        Substrate {
+            inputs,
+            hidden_layers,
+            outputs,
+        }
+    }
+
+    /// How many coordinates every node has
+    pub fn dimensions(&self) -> usize {
+        self.inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|position| position.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The CPPN is given the coordinates of both ends of a connection
+    pub fn cppn_inputs(&self) -> u32 {
+        2 * self.dimensions() as u32
+    }
+
+    /// The CPPN answers with the weight of the connection
+    pub fn cppn_outputs(&self) -> u32 {
+        1
+    }
+
+    /// Queries the CPPN for the weight of a connection between two positions
+    pub(crate) fn query(&self, cppn: &mut Network, from: &[f64], to: &[f64]) -> f64 {
+        let dimensions = self.dimensions();
+        let coordinate = |position: &[f64], i: usize| *position.get(i).unwrap_or(&0.);
+        let inputs: Vec<f64> = (0..dimensions)
+            .map(|i| coordinate(from, i))
+            .chain((0..dimensions).map(|i| coordinate(to, i)))
+            .collect();
+
+        cppn.reset_state();
+        cppn.forward_pass(&inputs)[0]
+    }
+
+    /// Paints the substrate with the weights queried from a CPPN genome
+    pub fn build(&self, cppn: &Genome, expression_threshold: f64, max_weight: f64) -> Network {
+        let mut cppn = Network::from_genome(cppn);
+
+        let mut layers: Vec<(NodeKind, &[Vec<f64>])> = vec![(NodeKind::Input, &self.inputs)];
+        for layer in &self.hidden_layers {
+            layers.push((NodeKind::Hidden, layer));
+        }
+        layers.push((NodeKind::Output, &self.outputs));
+
+        let mut nodes = vec![];
+        let mut node_positions = vec![];
+        let mut connections = vec![];
+        let mut previous_layer: Vec<(u32, &Vec<f64>)> = vec![];
+        for (kind, positions) in layers {
+            let layer: Vec<(u32, &Vec<f64>)> = positions
+                .iter()
+                .map(|position| {
+                    nodes.push(substrate_node(kind.clone()));
+                    node_positions.push(position.clone());
+                    (nodes.len() as u32 - 1, position)
+                })
+                .collect();
+
+            for (to, to_position) in &layer {
+                for (from, from_position) in &previous_layer {
+                    let output = self.query(&mut cppn, from_position, to_position);
+                    if let Some(weight) = express(output, expression_threshold, max_weight) {
+                        connections.push(Connection {
+                            from: *from,
+                            to: *to,
+                            weight,
+                        });
+                    }
+                }
+            }
+
+            // Empty hidden layers are skipped instead of disconnecting the outputs
+            if !layer.is_empty() {
+                previous_layer = layer;
+            }
+        }
+
+        let mut network = Network::feed_forward(
+            self.inputs.len() as u32,
+            self.outputs.len() as u32,
+            nodes,
+            connections,
+        );
+        network.node_positions = node_positions;
+        network
+    }
+}
+
+pub(super) fn substrate_node(kind: NodeKind) -> NeuralNode {
+    let activation = match kind {
+        NodeKind::Input => ActivationKind::Input,
+        _ => ActivationKind::Tanh,
+    };
+
+    NeuralNode {
+        kind,
+        aggregation: Aggregation::Sum,
+        activation,
+        bias: 0.,
+        value: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::linear_cppn;
+    use super::*;
+
+    /// Paints every connection with the x coordinate of its target
+    fn target_x_cppn() -> Genome {
+        linear_cppn(&[0., 0., 1., 0.], ActivationKind::Identity)
+    }
+
+    #[test]
+    fn cppn_sees_both_ends_of_a_connection() {
+        let substrate = Substrate::new(vec![vec![0., 0.]], vec![], vec![vec![0., 1.]]);
+
+        assert_eq!(substrate.dimensions(), 2);
+        assert_eq!(substrate.cppn_inputs(), 4);
+        assert_eq!(substrate.cppn_outputs(), 1);
+    }
+
+    #[test]
+    fn build_paints_expressed_weights() {
+        let substrate = Substrate::new(
+            vec![vec![-1., -1.], vec![1., -1.]],
+            vec![],
+            vec![vec![0.75, 1.], vec![-0.25, 1.]],
+        );
+        let mut network = substrate.build(&target_x_cppn(), 0.5, 3.);
+
+        assert_eq!(network.nodes.len(), 4);
+        assert_eq!(network.node_positions[2], vec![0.75, 1.]);
+        let connections: Vec<(u32, u32, f64)> = network
+            .connections
+            .iter()
+            .map(|c| (c.from, c.to, c.weight))
+            .collect();
+        assert_eq!(connections, vec![(0, 2, 1.5), (1, 2, 1.5)]);
+        assert_eq!(network.forward_pass(&[1., 0.]), vec![1.5f64.tanh(), 0.]);
+    }
+
+    #[test]
+    fn build_connects_layer_by_layer() {
+        let substrate = Substrate::new(
+            vec![vec![-1., -1.], vec![1., -1.]],
+            vec![vec![vec![0.5, 0.], vec![1., 0.], vec![0.9, 0.]], vec![]],
+            vec![vec![0.5, 1.]],
+        );
+        let network = substrate.build(&target_x_cppn(), 0.2, 3.);
+
+        assert_eq!(network.nodes.len(), 6);
+        // Inputs to all three hidden nodes, the empty layer is skipped
+        // and every hidden node connects to the output
+        assert_eq!(network.connections.len(), 2 * 3 + 3);
+        assert!(network.connections.iter().all(|c| c.from < 2 || c.to == 5));
+    }
+}
@@ -1,40 +1,26 @@
 mod activation;
 mod aggregations;
+#[cfg(feature = "godot")]
+mod bindings;
 mod connection;
 mod genome;
-mod map;
+mod hyperneat;
 mod mutations;
 mod neat;
 mod network;
 mod node;
+mod random;
 mod speciation;
 
-pub use genome::*;
-pub use map::*;
+pub use activation::ActivationKind;
+pub use aggregations::Aggregation;
+pub use connection::Connection;
+pub use genome::{
+    crossover, ConnectionGene, Genome, GenomeId, GenomeMap, InnovationTracker, NodeGene,
+};
+pub use hyperneat::Substrate;
+pub use mutations::MutationKind;
 pub use neat::*;
 pub use network::*;
-pub use node::EnumConversion;
-
-use godot::prelude::*;
-struct Lib;
-
-#[macro_export]
-macro_rules! bind {
-  ($($x:ident),*) => {
-      $(
-          let $x = $x.bind();
-      )*
-  };
-}
-
-#[macro_export]
-macro_rules! bind_mut {
-  ($($x:ident),*) => {
-      $(
-          let mut $x = $x.bind_mut();
-      )*
-  };
-}
-
-#[gdextension]
-unsafe impl ExtensionLibrary for Lib {}
+pub use node::{EnumConversion, NeuralNode, NodeKind};
+pub use speciation::{Species, SpeciesSet};
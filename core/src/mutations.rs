@@ -1,25 +1,31 @@
-use godot::prelude::utilities::{randf, randfn, randi, randi_range};
+use crate::random::Rng;
 
-use crate::genome::Genome;
+use crate::genome::{Genome, InnovationTracker};
 use crate::node::NodeKind;
 use crate::{EnumConversion, NodeGene};
-
-pub fn mutate(kind: &MutationKind, g: &mut Genome) {
+use nanoserde::{DeBin, SerBin};
+
+pub fn mutate(
+    kind: &MutationKind,
+    g: &mut Genome,
+    innovations: &mut InnovationTracker,
+    rng: &mut Rng,
+) {
     use MutationKind::*;
 
     match kind {
-        AddConnection => add_connection(g),
-        RemoveConnection => disable_connection(g),
-        AddNode => add_node(g),
-        RemoveNode => remove_node(g),
-        ModifyWeight => change_weight(g),
-        ModifyBias => change_bias(g),
-        ModifyActivation => change_activation(g),
-        ModifyAggregation => change_aggregation(g),
+        AddConnection => add_connection(g, innovations, rng),
+        RemoveConnection => disable_connection(g, rng),
+        AddNode => add_node(g, innovations, rng),
+        RemoveNode => remove_node(g, innovations, rng),
+        ModifyWeight => change_weight(g, rng),
+        ModifyBias => change_bias(g, rng),
+        ModifyActivation => change_activation(g, rng),
+        ModifyAggregation => change_aggregation(g, rng),
     };
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[derive(Debug, Clone, Eq, PartialEq, Copy, DeBin, SerBin)]
 pub enum MutationKind {
     AddConnection,
     RemoveConnection,
@@ -32,51 +38,54 @@ pub enum MutationKind {
 }
 
 #[inline]
-pub fn rand<T: EnumConversion>() -> T {
+pub fn rand<T: EnumConversion>(rng: &mut Rng) -> T {
     let (from, to) = T::pick_range();
-    T::from(randi_range(from, to) as u8)
+    T::from(rng.randi_range(from, to) as u8)
 }
 
 pub trait Pick<T> {
-    fn randi(&self) -> usize;
-    fn rande(&self) -> &T;
+    fn randi(&self, rng: &mut Rng) -> usize;
+    fn rande(&self, rng: &mut Rng) -> &T;
 }
 impl<T> Pick<T> for [T] {
     #[inline]
-    fn rande(&self) -> &T {
-        self.get(self.randi()).unwrap()
+    fn rande(&self, rng: &mut Rng) -> &T {
+        self.get(self.randi(rng)).unwrap()
     }
     #[inline]
-    fn randi(&self) -> usize {
-        randi() as usize % self.len()
+    fn randi(&self, rng: &mut Rng) -> usize {
+        rng.randi() as usize % self.len()
     }
 }
 
-fn get_node_gene(g: &mut Genome) -> &mut NodeGene {
-    let eligible: Vec<usize> = g
+fn get_node_gene<'a>(g: &'a mut Genome, rng: &mut Rng) -> &'a mut NodeGene {
+    let eligible: Vec<u32> = g
         .nodes()
         .iter()
-        .enumerate()
-        .filter(|(_, n)| !matches!(n.kind, NodeKind::Input))
-        .map(|(i, _)| i)
+        .filter(|n| !matches!(n.kind, NodeKind::Input | NodeKind::Constant))
+        .map(|n| n.id)
         .collect();
-    g.node_mut(*eligible.rande()).unwrap()
+    g.node_mut(*eligible.rande(rng)).unwrap()
 }
 
 /// Adds a new random connection
-pub fn add_connection(g: &mut Genome) {
+pub fn add_connection(g: &mut Genome, innovations: &mut InnovationTracker, rng: &mut Rng) {
     let existing_connections: Vec<(u32, u32, bool)> = g
         .connections()
         .iter()
         .map(|c| (c.from, c.to, c.disabled))
         .collect();
 
-    let mut possible_connections: Vec<(u32, u32)> = (0..g.nodes().len() as u32)
-        .flat_map(|i| {
+    let node_ids: Vec<u32> = g.nodes().iter().map(|n| n.id).collect();
+
+    let mut possible_connections: Vec<(u32, u32)> = node_ids
+        .iter()
+        .flat_map(|&i| {
             let mut inner = vec![];
 
-            (0..g.nodes().len() as u32).for_each(|j| {
-                if i != j {
+            node_ids.iter().for_each(|&j| {
+                // Self loops are only possible in recurrent genomes
+                if i != j || g.is_recurrent() {
                     if !existing_connections.contains(&(i, j, false)) {
                         inner.push((i, j));
                     };
@@ -99,14 +108,14 @@ pub fn add_connection(g: &mut Genome) {
         return;
     }
 
-    let picked_connection = possible_connections.rande();
+    let (from, to) = *possible_connections.rande(rng);
 
-    g.add_connection(picked_connection.0, picked_connection.1)
+    g.add_connection(from, to, innovations.connection(from, to), rng)
         .unwrap();
 }
 
 /// Removes a random connection if it's not the only one
-fn disable_connection(g: &mut Genome) {
+fn disable_connection(g: &mut Genome, rng: &mut Rng) {
     let eligible_indexes: Vec<usize> = g
         .connections()
         .iter()
@@ -141,15 +150,13 @@ fn disable_connection(g: &mut Genome) {
         return;
     }
 
-    let index = eligible_indexes.rande();
+    let index = eligible_indexes.rande(rng);
 
     g.disable_connection(*index);
 }
 
 /// Adds a random hidden node to the genome and its connections
-pub fn add_node(g: &mut Genome) {
-    let new_node_index = g.add_node();
-
+pub fn add_node(g: &mut Genome, innovations: &mut InnovationTracker, rng: &mut Rng) {
     // Only enabled connections can be disabled
     let enabled_connections: Vec<usize> = g
         .connections()
@@ -159,8 +166,12 @@ pub fn add_node(g: &mut Genome) {
         .map(|(i, _)| i)
         .collect();
 
-    let (picked_index, picked_from, picked_to, picked_weight) = {
-        let picked_index = enabled_connections.rande();
+    if enabled_connections.is_empty() {
+        return;
+    }
+
+    let (picked_index, picked_from, picked_to, picked_weight, picked_innovation) = {
+        let picked_index = enabled_connections.rande(rng);
         let picked_connection = g.connections().get(*picked_index).unwrap();
 
         (
@@ -168,28 +179,47 @@ pub fn add_node(g: &mut Genome) {
             picked_connection.from,
             picked_connection.to,
             picked_connection.weight,
+            picked_connection.innovation,
         )
     };
 
-    g.disable_connection(*picked_index);
+    // Splitting the same connection yields the same node within a generation
+    let mut new_node_id = innovations.split(picked_innovation);
+    if g.has_node(new_node_id) {
+        new_node_id = innovations.node();
+    }
+    g.add_node(new_node_id, rng);
 
     let connection_index = g
-        .add_connection(picked_from, new_node_index as u32)
+        .add_connection(
+            picked_from,
+            new_node_id,
+            innovations.connection(picked_from, new_node_id),
+            rng,
+        )
         .unwrap();
-    g.add_connection(new_node_index as u32, picked_to).unwrap();
+    g.add_connection(
+        new_node_id,
+        picked_to,
+        innovations.connection(new_node_id, picked_to),
+        rng,
+    )
+    .unwrap();
 
     // Reuse the weight from the removed connection
     g.connection_mut(connection_index).unwrap().weight = picked_weight;
+
+    // Disabled only now, until then it keeps the distance of `picked_to` past the new node
+    g.disable_connection(*picked_index);
 }
 
 /// Removes a random hidden node from the genome and rewires connected nodes
-fn remove_node(g: &mut Genome) {
+fn remove_node(g: &mut Genome, innovations: &mut InnovationTracker, rng: &mut Rng) {
     let hidden_nodes: Vec<u32> = g
         .nodes()
         .iter()
-        .enumerate()
-        .filter(|(i, n)| {
-            let i = *i as u32;
+        .filter(|n| {
+            let i = n.id;
             let incoming_count = g
                 .connections()
                 .iter()
@@ -203,31 +233,31 @@ fn remove_node(g: &mut Genome) {
 
             matches!(n.kind, NodeKind::Hidden) && incoming_count > 0 && outgoing_count > 0
         })
-        .map(|(i, _)| i as u32)
+        .map(|n| n.id)
         .collect();
 
     if hidden_nodes.is_empty() {
         return;
     }
 
-    let picked_node_index = hidden_nodes.rande();
+    let picked_node_id = hidden_nodes.rande(rng);
 
     let incoming_connections_and_from_indexes: Vec<(u32, u32)> = g
         .connections()
         .iter()
         .enumerate()
-        .filter(|(_, c)| c.to == *picked_node_index && !c.disabled)
+        .filter(|(_, c)| c.to == *picked_node_id && !c.disabled)
         .map(|(i, c)| (i as u32, c.from))
         .collect();
     let outgoing_connections_and_to_indexes: Vec<(u32, u32)> = g
         .connections()
         .iter()
         .enumerate()
-        .filter(|(_, c)| c.from == *picked_node_index && !c.disabled)
+        .filter(|(_, c)| c.from == *picked_node_id && !c.disabled)
         .map(|(i, c)| (i as u32, c.to))
         .collect();
 
-    let new_from_to_pairs: Vec<(u32, u32)> = incoming_connections_and_from_indexes
+    let new_from_to_pairs: Vec<(u32, u32, u32)> = incoming_connections_and_from_indexes
         .iter()
         .flat_map(|(_, from)| {
             outgoing_connections_and_to_indexes
@@ -235,20 +265,23 @@ fn remove_node(g: &mut Genome) {
                 .map(|(_, to)| (*from, *to))
                 .collect::<Vec<(u32, u32)>>()
         })
+        // Self loops on the removed node are not rewired
+        .filter(|(from, to)| *from != *picked_node_id && *to != *picked_node_id)
         .filter(|(from, to)| {
             !g.connections()
                 .iter()
                 .any(|c| c.from == *from && c.to == *to && !c.disabled)
         })
+        .map(|(from, to)| (from, to, innovations.connection(from, to)))
         .collect();
 
-    g.add_many_connections(&new_from_to_pairs);
+    g.add_many_connections(&new_from_to_pairs, rng);
 
     let connection_indexes_to_delete: Vec<usize> = g
         .connections()
         .iter()
         .enumerate()
-        .filter(|(_, c)| c.from == *picked_node_index || c.to == *picked_node_index)
+        .filter(|(_, c)| c.from == *picked_node_id || c.to == *picked_node_id)
         .map(|(i, _)| i)
         .collect();
 
@@ -256,41 +289,102 @@ fn remove_node(g: &mut Genome) {
 }
 
 /// Changes the weight of a random connection
-fn change_weight(g: &mut Genome) {
-    let index = g.connections().randi();
+fn change_weight(g: &mut Genome, rng: &mut Rng) {
+    if g.connections().is_empty() {
+        return;
+    }
+
+    let index = g.connections().randi(rng);
     let picked_connection = g.connection_mut(index).unwrap();
 
-    let new_weight = if randf() < 0.1 {
-        picked_connection.weight + randfn(0.5, 0.2)
+    let new_weight = if rng.randf() < 0.1 {
+        picked_connection.weight + rng.randfn(0.5, 0.2)
     } else {
-        randf() * 2.0 - 1.0
+        rng.randf() * 2.0 - 1.0
     };
 
-    picked_connection.weight = new_weight.max(-1.).min(1.);
+    picked_connection.weight = new_weight.clamp(-1., 1.);
 }
 
 /// Changes the bias of a random non input node
-fn change_bias(g: &mut Genome) {
-    let picked_node = get_node_gene(g);
+fn change_bias(g: &mut Genome, rng: &mut Rng) {
+    let picked_node = get_node_gene(g, rng);
 
-    let new_bias = if randf() < 0.1 {
-        picked_node.bias + randfn(0.5, 0.2)
+    let new_bias = if rng.randf() < 0.1 {
+        picked_node.bias + rng.randfn(0.5, 0.2)
     } else {
-        randf() * 2. - 1.
+        rng.randf() * 2. - 1.
     };
 
-    picked_node.bias = new_bias.max(-1.).min(1.);
+    picked_node.bias = new_bias.clamp(-1., 1.);
 }
 
 /// Changes the activation function of a random non input node
-fn change_activation(g: &mut Genome) {
-    let picked_node = get_node_gene(g);
+fn change_activation(g: &mut Genome, rng: &mut Rng) {
+    let picked_node = get_node_gene(g, rng);
+
+    picked_node.activation = rand(rng);
+}
+
+fn change_aggregation(g: &mut Genome, rng: &mut Rng) {
+    let picked_node = get_node_gene(g, rng);
 
-    picked_node.activation = rand();
+    picked_node.aggregation = rand(rng);
 }
 
-fn change_aggregation(g: &mut Genome) {
-    let picked_node = get_node_gene(g);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::ActivationKind;
+    use crate::aggregations::Aggregation;
+    use crate::connection::Connection;
+    use crate::network::Network;
+    use crate::node::NeuralNode;
+
+    /// The output is reached directly and through two hidden nodes, crossover can combine
+    /// such genomes even though `can_connect` never builds them
+    fn shortcut_genome(innovations: &mut InnovationTracker, rng: &mut Rng) -> Genome {
+        let node = |kind| NeuralNode {
+            kind,
+            aggregation: Aggregation::Sum,
+            activation: ActivationKind::Identity,
+            bias: 0.,
+            value: None,
+        };
+        let connection = |from, to| Connection {
+            from,
+            to,
+            weight: 1.,
+        };
+        let network = Network::feed_forward(
+            1,
+            1,
+            vec![
+                node(NodeKind::Input),
+                node(NodeKind::Hidden),
+                node(NodeKind::Hidden),
+                node(NodeKind::Output),
+            ],
+            vec![
+                connection(0, 3),
+                connection(0, 1),
+                connection(1, 2),
+                connection(2, 3),
+            ],
+        );
+        Genome::from_network(&network, false, innovations, rng)
+    }
+
+    #[test]
+    fn splitting_a_connection_keeps_the_genome_acyclic() {
+        for seed in 0..20 {
+            let (mut innovations, mut rng) = (InnovationTracker::new(), Rng::new(seed));
+            let mut g = shortcut_genome(&mut innovations, &mut rng);
 
-    picked_node.aggregation = rand();
+            for _ in 0..5 {
+                add_node(&mut g, &mut innovations, &mut rng);
+                assert!(g.node_order().is_some());
+            }
+        }
+    }
 }
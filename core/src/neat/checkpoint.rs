@@ -0,0 +1,184 @@
+use nanoserde::{DeBin, SerBin};
+use std::io;
+use std::path::Path;
+
+use super::{Configuration, EliteMap, GenomeBank, HallOfFame, NoveltyArchive, Statistics, NEAT};
+use crate::genome::{Genome, GenomeMap, InnovationTracker};
+use crate::hyperneat::Substrate;
+use crate::random::Rng;
+use crate::speciation::{Species, SpeciesSet};
+
+/// Everything needed to resume an evolution run
+///
+/// The generator state and the seed genomes are included, so a resumed run continues exactly
+/// like the original would, also when it is initialized again.
+#[derive(DeBin, SerBin)]
+pub struct Checkpoint {
+    inputs: u32,
+    outputs: u32,
+    generation: u32,
+    configuration: Configuration,
+    genomes: Vec<Genome>,
+    previous_genomes: Vec<Genome>,
+    species: Vec<(u32, Species)>,
+    compatibility_threshold: Option<f64>,
+    innovations: InnovationTracker,
+    statistics: Statistics,
+    hall_of_fame: HallOfFame,
+    novelty_archive: NoveltyArchive,
+    elite_map: Vec<(Vec<u32>, Genome)>,
+    substrate: Option<Substrate>,
+    seeds: Vec<Genome>,
+    seed: u64,
+    rng: Rng,
+}
+
+fn genome_map(genomes: Vec<Genome>) -> GenomeMap {
+    genomes
+        .into_iter()
+        .map(|genome| (genome.id(), genome))
+        .collect()
+}
+
+impl NEAT {
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            generation: self.generation,
+            configuration: self.configuration.clone(),
+            genomes: self.genomes.genomes().values().cloned().collect(),
+            previous_genomes: self.genomes.previous_genomes().values().cloned().collect(),
+            species: self
+                .species_set
+                .species()
+                .iter()
+                .map(|(id, species)| (*id, species.clone()))
+                .collect(),
+            compatibility_threshold: self.species_set.adjusted_compatibility_threshold(),
+            innovations: self.innovations.clone(),
+            statistics: self.statistics.clone(),
+            hall_of_fame: self.hall_of_fame.clone(),
+            novelty_archive: self.novelty_archive.clone(),
+            elite_map: self
+                .elite_map
+                .cells()
+                .iter()
+                .map(|(cell, genome)| (cell.clone(), genome.clone()))
+                .collect(),
+            substrate: self.substrate.clone(),
+            seeds: self.seeds.clone(),
+            seed: self.seed,
+            rng: self.rng.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        self.inputs = checkpoint.inputs;
+        self.outputs = checkpoint.outputs;
+        self.generation = checkpoint.generation;
+        self.configuration = checkpoint.configuration;
+        self.genomes = GenomeBank::from_genomes(
+            genome_map(checkpoint.genomes),
+            genome_map(checkpoint.previous_genomes),
+        );
+        self.species_set = SpeciesSet::from_species(
+            checkpoint.species.into_iter().collect(),
+            checkpoint.compatibility_threshold,
+        );
+        self.innovations = checkpoint.innovations;
+        self.statistics = checkpoint.statistics;
+        self.hall_of_fame = checkpoint.hall_of_fame;
+        self.novelty_archive = checkpoint.novelty_archive;
+        self.elite_map = EliteMap::from_cells(checkpoint.elite_map.into_iter().collect());
+        self.substrate = checkpoint.substrate;
+        self.seeds = checkpoint.seeds;
+        self.seed = checkpoint.seed;
+        self.rng = checkpoint.rng;
+    }
+
+    /// Saves the whole run, so it can be resumed with `load_checkpoint`
+    pub fn save_checkpoint(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.checkpoint().serialize_bin())
+    }
+
+    /// Resumes a run saved with `save_checkpoint`
+    pub fn load_checkpoint(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes = std::fs::read(path)?;
+        let checkpoint = Checkpoint::deserialize_bin(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}")))?;
+        self.restore(checkpoint);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::xor;
+    use super::*;
+
+    fn fitnesses(neat: &NEAT) -> Vec<f64> {
+        let mut fitnesses: Vec<f64> = neat
+            .genomes
+            .genomes()
+            .values()
+            .map(|genome| genome.fitness())
+            .collect();
+        fitnesses.sort_by(|a, b| a.total_cmp(b));
+        fitnesses
+    }
+
+    #[test]
+    fn resumed_run_continues_like_the_original() {
+        let mut original = NEAT::new(2, 1);
+        original.set_seed(7);
+        original.configuration.population_size = 30;
+        original.initialize(&mut xor);
+        original.step(&mut xor);
+
+        let bytes = original.checkpoint().serialize_bin();
+        let mut resumed = NEAT::new(2, 1);
+        resumed.restore(Checkpoint::deserialize_bin(&bytes).unwrap());
+
+        for _ in 0..3 {
+            original.step(&mut xor);
+            resumed.step(&mut xor);
+        }
+        assert_eq!(resumed.generation(), original.generation());
+        assert_eq!(fitnesses(&resumed), fitnesses(&original));
+    }
+
+    #[test]
+    fn checkpoint_keeps_the_generator_state() {
+        let mut neat = NEAT::new(2, 1);
+        neat.set_seed(7);
+        neat.configuration.population_size = 30;
+        neat.initialize(&mut xor);
+
+        let mut restored = NEAT::new(2, 1);
+        restored.set_seed(8);
+        restored.restore(neat.checkpoint());
+        assert_eq!(restored.rng.randi(), neat.rng.randi());
+    }
+
+    #[test]
+    fn checkpoint_keeps_the_seed_genomes() {
+        let mut neat = NEAT::new(2, 1);
+        neat.set_seed(7);
+        neat.configuration.population_size = 30;
+        neat.add_seed(Genome::new(
+            2,
+            1,
+            false,
+            &mut InnovationTracker::new(),
+            &mut Rng::new(0),
+        ));
+
+        let mut restored = NEAT::new(2, 1);
+        restored.restore(neat.checkpoint());
+        assert_eq!(restored.seeds, neat.seeds);
+        neat.initialize(&mut xor);
+        restored.initialize(&mut xor);
+        assert_eq!(fitnesses(&restored), fitnesses(&neat));
+    }
+}
@@ -1,48 +1,138 @@
+#[cfg(feature = "godot")]
 use godot::prelude::*;
+use nanoserde::{DeBin, SerBin};
 
+use super::elite_map::GridDimension;
 use crate::mutations::MutationKind;
+use crate::EnumConversion;
 
 /// Holds configuration options of the whole NEAT process
-#[derive(GodotClass)]
-#[class(base=RefCounted)]
+#[derive(Debug, Clone, DeBin, SerBin)]
+#[cfg_attr(feature = "godot", derive(GodotClass), class(base=RefCounted))]
 pub struct Configuration {
     /// The generations limit of for the evolution process
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub max_generations: u32,
 
     /// The maximum number of genomes in each generation
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub population_size: u32,
 
     /// The ratio of champion individuals that are copied to the next generation
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub elitism: f64,
 
     /// The minimum amount of species that need to exist after the removal of stagnated ones
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub elitism_species: u32,
 
     /// How many generations of not making progress is considered stagnation
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub stagnation_after: u32,
 
     /// The fitness cost of every node in the gene
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub node_cost: f64,
 
     /// The fitness cost of every connection in the gene
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub connection_cost: f64,
 
     /// The mutation rate of offspring
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub mutation_rate: f64,
 
     /// The ratio of genomes that will survive to the next generation
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub survival_ratio: f64,
 
-    /// The types of mutations available and their sampling weights
+    /// Allows recurrent and self-loop connections, giving networks memory between passes
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub allow_recurrent: bool,
+
+    /// How the genomes of the initial population are connected, unless it comes from seeds
+    pub initial_topology: InitialTopology,
+
+    /// The chance of every input to output connection with `InitialTopology::RandomFraction`
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub initial_connection_fraction: f64,
+
+    /// The size of the hidden layer with `InitialTopology::OneHiddenLayer`
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub initial_hidden_nodes: u32,
+
+    /// Gives genomes a bias node, a constant input of 1 that connects like the other inputs
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub bias_node: bool,
+
+    /// Lets every node evolve its own bias, turn it off along with `bias_node` for classic NEAT
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub node_biases: bool,
+
+    /// How many of the best genomes ever seen are kept, 0 disables the hall of fame
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub hall_of_fame_size: u32,
+
+    /// How much novelty counts in selection compared to fitness, 0 ignores novelty and 1 ignores fitness
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub novelty_weight: f64,
+
+    /// How many nearest behaviors the novelty of a behavior is measured against
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub novelty_neighbors: u32,
+
+    /// Behaviors at least this novel are added to the novelty archive
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub novelty_archive_threshold: f64,
+
+    /// Whether generations come from species or from the MAP-Elites grid
+    pub run_mode: RunMode,
+
+    /// The axes of the MAP-Elites grid, the evaluator returns one descriptor for each of them
+    pub map_elites_dimensions: Vec<GridDimension>,
+
+    /// HyperNEAT only expresses connections whose CPPN output is further from 0 than this
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub hyperneat_expression_threshold: f64,
+
+    /// The largest weight HyperNEAT paints on the substrate
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub hyperneat_max_weight: f64,
+
+    /// Discovers the hidden nodes with ES-HyperNEAT instead of using the hidden layers of the substrate
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_hyperneat: bool,
+
+    /// How many times the ES-HyperNEAT quadtree is split everywhere
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_initial_depth: u32,
+
+    /// How many times the ES-HyperNEAT quadtree can be split where the weights vary
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_max_depth: u32,
+
+    /// Squares whose weights vary more than this are split further, up to `es_max_depth`
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_division_threshold: f64,
+
+    /// Squares whose weights vary less than this are considered for a hidden node
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_variance_threshold: f64,
+
+    /// How much a square has to differ from its neighbors to become a hidden node,
+    /// higher values find fewer hidden nodes in the early, mostly smooth CPPN patterns
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_band_threshold: f64,
+
+    /// How many times hidden nodes are searched for starting from the hidden nodes found before
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub es_iteration_level: u32,
+
+    /// How offspring are divided between species
+    pub offspring_allocation: OffspringAllocation,
+
+    /// The types of mutations available and their sampling weights, `ModifyBias` is skipped
+    /// without `node_biases`
     pub mutation_kinds: Vec<(MutationKind, usize)>,
 
     /// The process will stop if the fitness goal is reached
@@ -52,29 +142,36 @@ pub struct Configuration {
      * Genomic distance during speciation
      */
     /// Controls how much connections can affect distance
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub distance_connection_disjoint_coefficient: f64,
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub distance_connection_weight_coeficcient: f64,
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub distance_connection_disabled_coefficient: f64,
 
     /// Controls how much nodes can affect distance
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub distance_node_bias_coefficient: f64,
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub distance_node_activation_coefficient: f64,
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub distance_node_aggregation_coefficient: f64,
 
     /// A limit on how distant two genomes can be to belong to the same species
-    #[export(get, set)]
+    #[cfg_attr(feature = "godot", export(get, set))]
     pub compatibility_threshold: f64,
+
+    /// Adjusts the compatibility threshold every generation to keep this many species, 0 keeps it fixed
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub target_species_count: u32,
+
+    /// How much the compatibility threshold moves per generation when targeting a species count
+    #[cfg_attr(feature = "godot", export(get, set))]
+    pub compatibility_threshold_step: f64,
 }
 
-#[godot_api]
-impl RefCountedVirtual for Configuration {
-    fn init(_base: Base<RefCounted>) -> Self {
+impl Default for Configuration {
+    fn default() -> Self {
         Configuration {
             max_generations: 1000,
             population_size: 150,
@@ -85,6 +182,28 @@ impl RefCountedVirtual for Configuration {
             connection_cost: 0.,
             mutation_rate: 0.5,
             survival_ratio: 0.5,
+            allow_recurrent: false,
+            initial_topology: InitialTopology::FullyConnected,
+            initial_connection_fraction: 0.5,
+            initial_hidden_nodes: 2,
+            bias_node: false,
+            node_biases: true,
+            hall_of_fame_size: 10,
+            novelty_weight: 0.,
+            novelty_neighbors: 15,
+            novelty_archive_threshold: 1.,
+            run_mode: RunMode::Speciated,
+            map_elites_dimensions: vec![],
+            hyperneat_expression_threshold: 0.2,
+            hyperneat_max_weight: 3.,
+            es_hyperneat: false,
+            es_initial_depth: 3,
+            es_max_depth: 5,
+            es_division_threshold: 0.03,
+            es_variance_threshold: 0.03,
+            es_band_threshold: 0.1,
+            es_iteration_level: 1,
+            offspring_allocation: OffspringAllocation::Softmax,
             mutation_kinds: default_mutation_kinds(),
             fitness_goal: None,
             distance_connection_disjoint_coefficient: 1.,
@@ -94,10 +213,112 @@ impl RefCountedVirtual for Configuration {
             distance_node_activation_coefficient: 0.33,
             distance_node_aggregation_coefficient: 0.33,
             compatibility_threshold: 3.,
+            target_species_count: 0,
+            compatibility_threshold_step: 0.3,
         }
     }
 }
 
+/// Ways of turning species fitness into the share of offspring each species gets
+#[derive(Debug, Clone, Copy, PartialEq, DeBin, SerBin)]
+pub enum OffspringAllocation {
+    /// Original NEAT, members share the fitness of their species
+    /// so offspring are proportional to the summed adjusted fitness
+    FitnessSharing,
+    /// Proportional to the exponent of mean species fitness
+    Softmax,
+    /// Proportional to the rank of mean species fitness, the scale of fitness does not matter
+    Rank,
+}
+
+impl EnumConversion for OffspringAllocation {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => Self::FitnessSharing,
+            1 => Self::Softmax,
+            _ => Self::Rank,
+        }
+    }
+    fn to(self) -> u8 {
+        match self {
+            Self::FitnessSharing => 0,
+            Self::Softmax => 1,
+            Self::Rank => 2,
+        }
+    }
+    fn len() -> u8 {
+        3
+    }
+}
+
+/// How the genomes of the initial population are connected
+#[derive(Debug, Clone, Copy, PartialEq, DeBin, SerBin)]
+pub enum InitialTopology {
+    /// Every input connected to every output
+    FullyConnected,
+    /// No connections, evolution adds all of them
+    Unconnected,
+    /// One connection from a random input to a random output, as in FS-NEAT,
+    /// so evolution selects the inputs that matter
+    SingleInput,
+    /// Every input to output connection exists with a chance of `initial_connection_fraction`
+    RandomFraction,
+    /// `initial_hidden_nodes` hidden nodes connected to every input and every output
+    OneHiddenLayer,
+}
+
+impl EnumConversion for InitialTopology {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => Self::FullyConnected,
+            1 => Self::Unconnected,
+            2 => Self::SingleInput,
+            3 => Self::RandomFraction,
+            _ => Self::OneHiddenLayer,
+        }
+    }
+    fn to(self) -> u8 {
+        match self {
+            Self::FullyConnected => 0,
+            Self::Unconnected => 1,
+            Self::SingleInput => 2,
+            Self::RandomFraction => 3,
+            Self::OneHiddenLayer => 4,
+        }
+    }
+    fn len() -> u8 {
+        5
+    }
+}
+
+/// How every generation after the first one is bred
+#[derive(Debug, Clone, Copy, PartialEq, DeBin, SerBin)]
+pub enum RunMode {
+    /// Species share the offspring and breed them with crossover, the original NEAT
+    Speciated,
+    /// Mutated copies of elites sampled from the MAP-Elites grid, building a repertoire
+    /// of genomes that behave differently instead of a single champion
+    MapElites,
+}
+
+impl EnumConversion for RunMode {
+    fn from(i: u8) -> Self {
+        match i {
+            0 => Self::Speciated,
+            _ => Self::MapElites,
+        }
+    }
+    fn to(self) -> u8 {
+        match self {
+            Self::Speciated => 0,
+            Self::MapElites => 1,
+        }
+    }
+    fn len() -> u8 {
+        2
+    }
+}
+
 pub fn default_mutation_kinds() -> Vec<(MutationKind, usize)> {
     use MutationKind::*;
 
@@ -112,15 +333,3 @@ pub fn default_mutation_kinds() -> Vec<(MutationKind, usize)> {
         (ModifyAggregation, 10),
     ]
 }
-
-#[godot_api]
-impl Configuration {
-    #[func]
-    fn set_fitness_goal(&mut self, to: Variant) {
-        if to.is_nil() {
-            self.fitness_goal = None
-        } else {
-            self.fitness_goal = Some(f64::from_variant(&to))
-        }
-    }
-}
@@ -0,0 +1,142 @@
+use nanoserde::{DeBin, SerBin};
+use std::collections::BTreeMap;
+
+use crate::genome::Genome;
+
+/// One axis of the MAP-Elites grid, behaviors outside of `[min, max]` land in the edge cells
+#[derive(Debug, Clone, PartialEq, DeBin, SerBin)]
+pub struct GridDimension {
+    pub min: f64,
+    pub max: f64,
+    pub cells: u32,
+}
+
+impl GridDimension {
+    pub fn new(min: f64, max: f64, cells: u32) -> Self {
+        GridDimension { min, max, cells }
+    }
+
+    /// The cell a descriptor value falls into
+    fn cell(&self, value: f64) -> u32 {
+        let cells = self.cells.max(1);
+        let position = (value - self.min) / (self.max - self.min) * cells as f64;
+        if position.is_nan() {
+            return 0;
+        }
+
+        (position.max(0.) as u32).min(cells - 1)
+    }
+}
+
+/// The MAP-Elites archive, the fittest genome found for every cell of the descriptor grid
+#[derive(Debug, Clone, Default)]
+pub struct EliteMap {
+    cells: BTreeMap<Vec<u32>, Genome>,
+}
+
+impl EliteMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An archive already holding these elites
+    pub fn from_cells(cells: BTreeMap<Vec<u32>, Genome>) -> Self {
+        EliteMap { cells }
+    }
+
+    /// Elites keyed by the coordinates of their cell
+    pub fn cells(&self) -> &BTreeMap<Vec<u32>, Genome> {
+        &self.cells
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The cell an evaluated genome belongs to, its behavior holds one descriptor per dimension
+    pub fn cell(dimensions: &[GridDimension], genome: &Genome) -> Vec<u32> {
+        assert_eq!(
+            genome.behavior.len(),
+            dimensions.len(),
+            "the evaluator needs to return one descriptor per grid dimension"
+        );
+
+        dimensions
+            .iter()
+            .zip(&genome.behavior)
+            .map(|(dimension, value)| dimension.cell(*value))
+            .collect()
+    }
+
+    /// Keeps the genome if its cell is empty or it beats the elite there, returns whether it was kept
+    pub fn insert(&mut self, dimensions: &[GridDimension], genome: &Genome) -> bool {
+        let cell = Self::cell(dimensions, genome);
+        match self.cells.get(&cell) {
+            Some(elite) if elite.fitness() >= genome.fitness() => false,
+            _ => {
+                self.cells.insert(cell, genome.clone());
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::InnovationTracker;
+    use crate::random::Rng;
+
+    fn genome(fitness: f64, behavior: Vec<f64>) -> Genome {
+        let mut genome = Genome::new(1, 1, false, &mut InnovationTracker::new(), &mut Rng::new(0));
+        genome.fitness = Some(fitness);
+        genome.behavior = behavior;
+        genome
+    }
+
+    fn dimensions() -> Vec<GridDimension> {
+        vec![
+            GridDimension::new(0., 1., 4),
+            GridDimension::new(-10., 10., 2),
+        ]
+    }
+
+    #[test]
+    fn descriptors_outside_the_range_land_in_the_edge_cells() {
+        let dimension = GridDimension::new(0., 1., 4);
+
+        assert_eq!(dimension.cell(0.3), 1);
+        assert_eq!(dimension.cell(1.), 3);
+        assert_eq!(dimension.cell(-5.), 0);
+        assert_eq!(dimension.cell(5.), 3);
+        assert_eq!(dimension.cell(f64::NAN), 0);
+    }
+
+    #[test]
+    fn genomes_are_placed_by_their_behavior() {
+        assert_eq!(
+            EliteMap::cell(&dimensions(), &genome(0., vec![0.6, 3.])),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn only_fitter_genomes_replace_an_elite() {
+        let mut map = EliteMap::new();
+
+        assert!(map.insert(&dimensions(), &genome(1., vec![0.1, -1.])));
+        assert!(!map.insert(&dimensions(), &genome(0.5, vec![0.2, -2.])));
+        assert!(!map.insert(&dimensions(), &genome(1., vec![0.2, -2.])));
+        assert!(map.insert(&dimensions(), &genome(2., vec![0.2, -2.])));
+        assert!(map.insert(&dimensions(), &genome(0., vec![0.9, 9.])));
+
+        assert_eq!(map.cells().len(), 2);
+        assert_eq!(map.cells()[&vec![0, 0]].fitness(), 2.);
+    }
+
+    #[test]
+    #[should_panic(expected = "one descriptor per grid dimension")]
+    fn behavior_needs_a_descriptor_per_dimension() {
+        EliteMap::cell(&dimensions(), &genome(0., vec![0.5]));
+    }
+}
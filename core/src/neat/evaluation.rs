@@ -0,0 +1,206 @@
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+use crate::network::Network;
+
+/// Assigns a fitness to every network of a generation
+///
+/// Any `FnMut(&mut Network) -> f64` closure is an evaluator that looks at one network at a time.
+pub trait Evaluator {
+    fn evaluate(&mut self, networks: &mut [Network]) -> Vec<f64>;
+
+    /// Everything measured about every network, novelty search and multi-objective
+    /// optimization need more than the fitness
+    ///
+    /// Evaluators that only assign fitness measure nothing else.
+    fn evaluate_all(&mut self, networks: &mut [Network]) -> Vec<Evaluation> {
+        self.evaluate(networks)
+            .into_iter()
+            .map(Evaluation::from)
+            .collect()
+    }
+}
+
+/// What the evaluation of a single network measured
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Evaluation {
+    pub fitness: f64,
+    /// Characterizes what the network did, used by novelty search
+    pub behavior: Vec<f64>,
+    /// Values that are all maximized, selection uses Pareto ranking when they are present
+    pub objectives: Vec<f64>,
+}
+
+impl From<f64> for Evaluation {
+    fn from(fitness: f64) -> Self {
+        Evaluation {
+            fitness,
+            ..Self::default()
+        }
+    }
+}
+
+impl<F> Evaluator for F
+where
+    F: FnMut(&mut Network) -> f64,
+{
+    fn evaluate(&mut self, networks: &mut [Network]) -> Vec<f64> {
+        networks.iter_mut().map(self).collect()
+    }
+}
+
+/// Evaluates networks on a thread pool
+///
+/// Uses rayon's global pool unless one with a fixed number of threads is requested.
+pub struct Parallel<F> {
+    fitness_fn: F,
+    pool: Option<ThreadPool>,
+}
+
+impl<F> Parallel<F>
+where
+    F: Fn(&mut Network) -> f64 + Sync,
+{
+    pub fn new(fitness_fn: F) -> Self {
+        Parallel {
+            fitness_fn,
+            pool: None,
+        }
+    }
+
+    pub fn with_threads(fitness_fn: F, threads: usize) -> Result<Self, ThreadPoolBuildError> {
+        Ok(Parallel {
+            fitness_fn,
+            pool: Some(ThreadPoolBuilder::new().num_threads(threads).build()?),
+        })
+    }
+}
+
+impl<F> Evaluator for Parallel<F>
+where
+    F: Fn(&mut Network) -> f64 + Sync,
+{
+    fn evaluate(&mut self, networks: &mut [Network]) -> Vec<f64> {
+        let fitness_fn = &self.fitness_fn;
+        let mut evaluate = move || networks.par_iter_mut().map(fitness_fn).collect();
+
+        match &self.pool {
+            Some(pool) => pool.install(evaluate),
+            None => evaluate(),
+        }
+    }
+}
+
+/// Evaluates the whole generation at once, for example when all agents share one simulation
+///
+/// The closure returns the fitnesses in the same order as the networks.
+pub struct Batch<F>(pub F);
+
+impl<F> Evaluator for Batch<F>
+where
+    F: FnMut(&mut [Network]) -> Vec<f64>,
+{
+    fn evaluate(&mut self, networks: &mut [Network]) -> Vec<f64> {
+        (self.0)(networks)
+    }
+}
+
+/// Scores one network at a time and also characterizes its behavior for novelty search
+///
+/// The closure returns the fitness and the behavior vector, pure novelty search can report 0 fitness.
+pub struct Behavior<F>(pub F);
+
+impl<F> Evaluator for Behavior<F>
+where
+    F: FnMut(&mut Network) -> (f64, Vec<f64>),
+{
+    fn evaluate(&mut self, networks: &mut [Network]) -> Vec<f64> {
+        networks
+            .iter_mut()
+            .map(|network| (self.0)(network).0)
+            .collect()
+    }
+
+    fn evaluate_all(&mut self, networks: &mut [Network]) -> Vec<Evaluation> {
+        networks
+            .iter_mut()
+            .map(|network| {
+                let (fitness, behavior) = (self.0)(network);
+                Evaluation {
+                    fitness,
+                    behavior,
+                    ..Evaluation::default()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Scores one network at a time on several objectives, which are all maximized
+///
+/// Selection ranks genomes by Pareto dominance, the reported fitness is the sum of the objectives.
+pub struct Objectives<F>(pub F);
+
+impl<F> Evaluator for Objectives<F>
+where
+    F: FnMut(&mut Network) -> Vec<f64>,
+{
+    fn evaluate(&mut self, networks: &mut [Network]) -> Vec<f64> {
+        networks
+            .iter_mut()
+            .map(|network| (self.0)(network).iter().sum())
+            .collect()
+    }
+
+    fn evaluate_all(&mut self, networks: &mut [Network]) -> Vec<Evaluation> {
+        networks
+            .iter_mut()
+            .map(|network| {
+                let objectives = (self.0)(network);
+                Evaluation {
+                    fitness: objectives.iter().sum(),
+                    objectives,
+                    ..Evaluation::default()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(count: u32) -> Vec<Network> {
+        (0..count)
+            .map(|input_count| Network::feed_forward(input_count, 0, vec![], vec![]))
+            .collect()
+    }
+
+    #[test]
+    fn parallel_evaluation_keeps_the_order_of_the_networks() {
+        let fitness_fn = |network: &mut Network| network.input_count as f64;
+        let mut parallel = Parallel::with_threads(fitness_fn, 4).unwrap();
+
+        assert_eq!(
+            parallel.evaluate(&mut networks(100)),
+            (0..100).map(f64::from).collect::<Vec<f64>>()
+        );
+    }
+
+    #[test]
+    fn batch_evaluation_scores_every_network_in_order() {
+        let mut batch = Batch(|networks: &mut [Network]| {
+            networks
+                .iter()
+                .map(|network| network.input_count as f64 * 2.)
+                .collect()
+        });
+
+        assert_eq!(batch.evaluate(&mut networks(4)), vec![0., 2., 4., 6.]);
+        assert_eq!(
+            batch.evaluate_all(&mut networks(2)),
+            vec![Evaluation::from(0.), Evaluation::from(2.)]
+        );
+    }
+}
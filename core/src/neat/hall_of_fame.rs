@@ -0,0 +1,106 @@
+use nanoserde::{DeBin, SerBin};
+
+use crate::genome::Genome;
+
+/// The best genomes seen across all generations of a run, fittest first
+#[derive(Debug, Clone, Default, DeBin, SerBin)]
+pub struct HallOfFame {
+    genomes: Vec<Genome>,
+}
+
+impl HallOfFame {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn genomes(&self) -> &[Genome] {
+        &self.genomes
+    }
+
+    pub fn best(&self) -> Option<&Genome> {
+        self.genomes.first()
+    }
+
+    /// Considers evaluated genomes for a place among the `size` best ones
+    pub fn update<'a>(&mut self, candidates: impl Iterator<Item = &'a Genome>, size: usize) {
+        for candidate in candidates {
+            let fitness = candidate.fitness();
+
+            // Elites keep their id between generations, only their best evaluation is kept
+            if let Some(existing) = self.genomes.iter_mut().find(|g| g.id() == candidate.id()) {
+                if fitness > existing.fitness() {
+                    *existing = candidate.clone();
+                }
+            } else {
+                self.genomes.push(candidate.clone());
+            }
+        }
+
+        self.genomes
+            .sort_by(|a, b| b.fitness().total_cmp(&a.fitness()));
+        self.genomes.truncate(size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::InnovationTracker;
+    use crate::random::Rng;
+
+    fn genomes(fitnesses: &[f64], rng: &mut Rng) -> Vec<Genome> {
+        fitnesses
+            .iter()
+            .map(|fitness| {
+                let mut genome = Genome::new(1, 1, false, &mut InnovationTracker::new(), rng);
+                genome.fitness = Some(*fitness);
+                genome
+            })
+            .collect()
+    }
+
+    fn fitnesses(hall_of_fame: &HallOfFame) -> Vec<f64> {
+        hall_of_fame.genomes().iter().map(|g| g.fitness()).collect()
+    }
+
+    #[test]
+    fn keeps_the_fittest_genomes_up_to_its_size() {
+        let mut hall_of_fame = HallOfFame::new();
+        hall_of_fame.update(genomes(&[3., 5., 1., 4., 2.], &mut Rng::new(0)).iter(), 3);
+
+        assert_eq!(fitnesses(&hall_of_fame), vec![5., 4., 3.]);
+        assert_eq!(hall_of_fame.best().unwrap().fitness(), 5.);
+    }
+
+    #[test]
+    fn weaker_entries_are_replaced() {
+        let (mut hall_of_fame, mut rng) = (HallOfFame::new(), Rng::new(0));
+        hall_of_fame.update(genomes(&[5., 4., 3.], &mut rng).iter(), 3);
+
+        hall_of_fame.update(genomes(&[4.5, 1.], &mut rng).iter(), 3);
+        assert_eq!(fitnesses(&hall_of_fame), vec![5., 4.5, 4.]);
+    }
+
+    #[test]
+    fn a_genome_is_only_listed_once_with_its_best_evaluation() {
+        let mut hall_of_fame = HallOfFame::new();
+        let mut genome = genomes(&[2.], &mut Rng::new(0)).remove(0);
+        hall_of_fame.update(std::iter::once(&genome), 3);
+
+        genome.fitness = Some(1.);
+        hall_of_fame.update(std::iter::once(&genome), 3);
+        assert_eq!(fitnesses(&hall_of_fame), vec![2.]);
+
+        genome.fitness = Some(3.);
+        hall_of_fame.update(std::iter::once(&genome), 3);
+        assert_eq!(fitnesses(&hall_of_fame), vec![3.]);
+    }
+
+    #[test]
+    fn a_size_of_zero_keeps_nothing() {
+        let mut hall_of_fame = HallOfFame::new();
+        hall_of_fame.update(genomes(&[1., 2.], &mut Rng::new(0)).iter(), 0);
+
+        assert!(hall_of_fame.best().is_none());
+    }
+}
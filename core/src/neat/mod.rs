@@ -1,318 +1,1022 @@
-use godot::prelude::utilities::randf;
-use godot::prelude::*;
-
-use crate::bind;
-use crate::genome::{crossover, Genome, GenomeId, GenomeMap};
-use crate::mutations::{MutationKind, Pick};
+use crate::genome::{crossover, Genome, GenomeId, InnovationTracker};
+use crate::hyperneat::Substrate;
+use crate::mutations::{self, MutationKind, Pick};
 use crate::network::Network;
+use crate::random::Rng;
 use crate::speciation::SpeciesSet;
-pub use configuration::Configuration;
-use speciation::GenomeBank;
-
+pub use checkpoint::Checkpoint;
+pub use configuration::{Configuration, InitialTopology, OffspringAllocation, RunMode};
+pub use elite_map::{EliteMap, GridDimension};
+pub use evaluation::{Batch, Behavior, Evaluation, Evaluator, Objectives, Parallel};
+pub use hall_of_fame::HallOfFame;
+pub use novelty::NoveltyArchive;
+pub use speciation::GenomeBank;
+pub use statistics::{GenerationStatistics, Statistics};
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+mod checkpoint;
 mod configuration;
+mod elite_map;
+mod evaluation;
+mod hall_of_fame;
+mod novelty;
+mod pareto;
 mod speciation;
-
-#[derive(GodotClass)]
-#[class(base=RefCounted)]
+mod statistics;
+
+/// The evolution process
+///
+/// Fitness is provided by an [`Evaluator`], usually a closure scoring a single network:
+///
+/// ```ignore
+/// let mut neat = NEAT::new(2, 1);
+/// let result = neat.start(&mut |network: &mut Network| {
+///     1. - (network.forward_pass(&[1., 0.])[0] - 1.).abs()
+/// });
+/// let (network, fitness) = (result.network, result.fitness);
+/// ```
+///
+/// Novelty search is enabled with `novelty_weight`, the evaluator then needs to characterize
+/// behaviors, for example with [`Behavior`]. Evaluating several objectives with [`Objectives`]
+/// makes selection rank genomes by Pareto dominance. [`RunMode::MapElites`] replaces species with
+/// a grid of elites, the behavior then holds the descriptors placing genomes in the grid.
+#[derive(Debug, Clone)]
 pub struct NEAT {
-    #[export(get, set)]
-    inputs: u32,
-    #[export(get, set)]
-    outputs: u32,
-    #[export(get, set)]
-    fitness_fn: Callable,
-    #[export(get, set)]
-    reporter_fn: Callable,
-    #[export(get, set)]
-    genomes: Gd<GenomeBank>,
-    #[export(get, set)]
-    species_set: Gd<SpeciesSet>,
-    #[export(get, set)]
-    configuration: Gd<Configuration>,
+    pub inputs: u32,
+    pub outputs: u32,
+    pub genomes: GenomeBank,
+    pub species_set: SpeciesSet,
+    pub configuration: Configuration,
+    pub statistics: Statistics,
+    pub hall_of_fame: HallOfFame,
+    pub novelty_archive: NoveltyArchive,
+    /// The repertoire built when running in [`RunMode::MapElites`]
+    pub elite_map: EliteMap,
+    /// Makes genomes CPPNs painting the weights of this substrate, which is the network evaluated
+    pub substrate: Option<Substrate>,
+    /// The generation evolution is currently at, 0 being the initial population
+    generation: u32,
+    innovations: InnovationTracker,
+    /// Genomes the initial population is copied from instead of being created from scratch
+    seeds: Vec<Genome>,
+    /// The seed `rng` is reset to when a run is initialized
+    seed: u64,
+    rng: Rng,
 }
 
-#[godot_api]
-impl RefCountedVirtual for NEAT {
-    fn init(_base: Base<Self::Base>) -> Self {
-        Self::default()
-    }
+/// What a whole run produced
+#[derive(Debug, Clone)]
+pub struct TrainingResult {
+    /// The best network ever seen
+    pub network: Network,
+    pub fitness: f64,
+    /// The Pareto front of the final generation, empty unless the evaluator returned objectives
+    pub pareto_front: Vec<Genome>,
+    /// The final MAP-Elites archive, networks keyed by the coordinates of their cell
+    pub elites: BTreeMap<Vec<u32>, Network>,
 }
 
 impl Default for NEAT {
     fn default() -> Self {
-        let configuration: Gd<Configuration> = Gd::new_default();
+        let seed = Rng::entropy_seed();
         NEAT {
             inputs: 0,
             outputs: 0,
-            fitness_fn: Callable::default(),
-            reporter_fn: Callable::default(),
-            genomes: Gd::new_default(),
-            species_set: Gd::new(SpeciesSet::new(configuration.share())),
-            configuration,
+            genomes: GenomeBank::new(),
+            species_set: SpeciesSet::new(),
+            configuration: Configuration::default(),
+            statistics: Statistics::new(),
+            hall_of_fame: HallOfFame::new(),
+            novelty_archive: NoveltyArchive::new(),
+            elite_map: EliteMap::new(),
+            substrate: None,
+            generation: 0,
+            innovations: InnovationTracker::new(),
+            seeds: vec![],
+            seed,
+            rng: Rng::new(seed),
         }
     }
 }
 
-#[derive(GodotClass)]
-#[class(base=RefCounted)]
-pub struct StartResult {
-    #[export(get, set)]
-    pub network: Gd<Network>,
-    #[export(get, set)]
-    pub best_fitness: f64,
-}
+impl NEAT {
+    pub fn new(inputs: u32, outputs: u32) -> Self {
+        NEAT {
+            inputs,
+            outputs,
+            ..Self::default()
+        }
+    }
 
-#[godot_api]
-impl StartResult {}
+    /// The generation evolution is currently at, 0 being the initial population
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
 
-#[derive(GodotClass)]
-#[class(base=RefCounted)]
-pub struct BestResult {
-    #[export(get, set)]
-    pub best_id: i64,
-    #[export(get, set)]
-    pub best_fitness: f64,
-}
+    /// The seed of the current run, picked from the system clock unless set explicitly
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-#[godot_api]
-impl BestResult {}
+    /// Runs initialized with the same seed and configuration evolve identically
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
 
-#[godot_api]
-impl NEAT {
-    #[func]
-    pub fn start(&mut self) -> Gd<StartResult> {
-        let (population_size, max_generations) = {
-            let config = self.configuration.bind();
-            (config.population_size, config.max_generations)
+    /// Creates and evaluates the initial population, discarding any previous run
+    pub fn initialize(&mut self, evaluator: &mut impl Evaluator) {
+        let population_size = self.configuration.population_size;
+        let (inputs, outputs) = match &self.substrate {
+            Some(substrate) => (substrate.cppn_inputs(), substrate.cppn_outputs()),
+            None => (self.inputs, self.outputs),
         };
 
+        self.genomes = GenomeBank::new();
+        self.species_set = SpeciesSet::new();
+        self.statistics = Statistics::new();
+        self.hall_of_fame = HallOfFame::new();
+        self.novelty_archive = NoveltyArchive::new();
+        self.elite_map = EliteMap::new();
+        self.innovations = InnovationTracker::new();
+        self.generation = 0;
+        self.rng = Rng::new(self.seed);
+
+        let mut seeds = self.seeds.clone();
+        seeds
+            .iter_mut()
+            .for_each(|seed| seed.register(&mut self.innovations));
+
         // Create initial genomes
-        (0..population_size).for_each(|_| {
-            self.genomes
-                .bind_mut()
-                .add_genome(Gd::new(Genome::new(self.inputs, self.outputs)))
-        });
+        for i in 0..population_size as usize {
+            let mut genome = if seeds.is_empty() {
+                initial_genome(
+                    inputs,
+                    outputs,
+                    &self.configuration,
+                    &mut self.innovations,
+                    &mut self.rng,
+                )
+            } else {
+                let mut genome = seeds[i % seeds.len()].duplicate(&mut self.rng);
+                // The first copy of every seed is kept intact
+                if i >= seeds.len() {
+                    let mutation = pick_mutation(&self.configuration, &mut self.rng);
+                    genome.mutate(&mutation, &mut self.innovations, &mut self.rng);
+                }
+                genome
+            };
+            if !self.configuration.node_biases {
+                genome.clear_biases();
+            }
+            self.genomes.add_genome(genome);
+        }
 
-        self.test_fitness();
+        let evaluation_time = self.test_fitness(evaluator);
+        let compatibility_threshold = self
+            .species_set
+            .compatibility_threshold(&self.configuration);
+        self.record_generation(compatibility_threshold, 0, evaluation_time);
+    }
 
-        for i in 1..=max_generations {
-            let current_genome_ids: Vec<GenomeId> = self.genomes.bind().genomes().keys().collect();
-            let previous_and_current_genomes = GenomeMap::from_vec(
-                self.genomes
-                    .bind()
-                    .genomes()
-                    .iter()
-                    .chain(self.genomes.bind().previous_genomes().iter())
-                    .collect(),
-            );
-            self.species_set.bind_mut().speciate(
-                i,
-                &current_genome_ids,
-                previous_and_current_genomes,
-            );
+    /// Seeds the initial population with mutated copies of the given genome
+    pub fn add_seed(&mut self, genome: Genome) {
+        self.seeds.push(genome);
+    }
 
-            let (elitism, population_size, mutation_rate, survival_ratio) = {
-                let config = self.configuration.bind();
+    /// Seeds the initial population with mutated copies of the given network
+    pub fn add_seed_network(&mut self, network: &Network) {
+        let allow_recurrent = self.configuration.allow_recurrent;
+        self.seeds.push(Genome::from_network(
+            network,
+            allow_recurrent,
+            &mut InnovationTracker::new(),
+            &mut self.rng,
+        ));
+    }
 
-                (
-                    config.elitism,
-                    config.population_size,
-                    config.mutation_rate,
-                    config.survival_ratio,
-                )
-            };
-            assert_ne!(self.species_set.bind().species().len(), 0);
-            let offspring: Vec<Gd<Genome>> = self
-                .species_set
-                .bind()
-                .species()
-                .values()
-                .flat_map(|species| {
-                    bind!(species);
-                    let offspring_count: usize = (species.adjusted_fitness.unwrap()
-                        * population_size as f64)
-                        .ceil() as usize;
-                    let elites_count: usize = (offspring_count as f64 * elitism).ceil() as usize;
-                    let nonelites_count: usize = offspring_count - elites_count;
-
-                    let mut member_ids_and_fitnesses: Vec<(GenomeId, f64)> = species
-                        .members
-                        .iter()
-                        .map(|member_id| {
-                            (
-                                *member_id,
-                                self.genomes
-                                    .bind()
-                                    .get(*member_id)
-                                    .unwrap()
-                                    .bind()
-                                    .fitness
-                                    .unwrap(),
-                            )
-                        })
-                        .collect();
-
-                    #[rustfmt::skip]
-                    member_ids_and_fitnesses.sort_by(|(_, a), (_, b)| {
-                        use std::cmp::Ordering::*;
-                        if a > b { Less } else { Greater }
-                    });
-
-                    // Pick survivors
-                    let surviving_count: usize =
-                        (member_ids_and_fitnesses.len() as f64 * survival_ratio).ceil() as usize;
-                    member_ids_and_fitnesses.truncate(surviving_count);
-
-                    let elite_children: Vec<Gd<Genome>> =
-                        (0..usize::min(elites_count, member_ids_and_fitnesses.len()))
-                            .map(|elite_index| {
-                                let (elite_genome_id, _) =
-                                    member_ids_and_fitnesses.get(elite_index).unwrap();
-                                self.genomes.bind().get(*elite_genome_id).unwrap()
-                            })
-                            .collect();
-
-                    let crossover_data: Vec<(Gd<Genome>, f64, Gd<Genome>, f64)> = (0
-                        ..nonelites_count)
-                        .map(|_| {
-                            let (parent_a_id, parent_a_fitness) = member_ids_and_fitnesses.rande();
-                            let (parent_b_id, parent_b_fitness) = member_ids_and_fitnesses.rande();
-
-                            (
-                                self.genomes.bind().get(*parent_a_id).unwrap(),
-                                *parent_a_fitness,
-                                self.genomes.bind().get(*parent_b_id).unwrap(),
-                                *parent_b_fitness,
-                            )
-                        })
-                        .collect();
-
-                    // TODO: rayon here
-                    let mut crossover_children: Vec<Gd<Genome>> = crossover_data
-                        .into_iter()
-                        .map(|(parent_a, fitness_a, parent_b, fitness_b)| {
-                            crossover((&parent_a.bind(), fitness_a), (&parent_b.bind(), fitness_b))
-                        })
-                        .filter(|maybe_genome| maybe_genome.is_some())
-                        .map(|maybe_genome| Gd::new(maybe_genome.unwrap()))
-                        .collect();
-
-                    let mutations_for_children: Vec<Option<MutationKind>> = crossover_children
-                        .iter()
-                        .map(|_| {
-                            if randf() < mutation_rate {
-                                Some(self.pick_mutation())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-
-                    // TODO: use par iter here
-                    crossover_children
-                        .iter_mut()
-                        .zip(mutations_for_children)
-                        .for_each(|(child, maybe_mutation)| {
-                            if let Some(mutation) = maybe_mutation {
-                                child.bind_mut().mutate(&mutation);
+    /// Goes back to creating the initial population from scratch
+    pub fn clear_seeds(&mut self) {
+        self.seeds.clear();
+    }
+
+    /// Runs a single generation: speciation, reproduction and evaluation
+    pub fn step(&mut self, evaluator: &mut impl Evaluator) {
+        assert!(
+            !self.genomes.genomes().is_empty(),
+            "initialize() needs to be called before step()"
+        );
+        self.generation += 1;
+        self.innovations.next_generation();
+        // Speciation moves the threshold when targeting a species count, record the one it used
+        let compatibility_threshold = self
+            .species_set
+            .compatibility_threshold(&self.configuration);
+
+        let stagnated_species_removed = match self.configuration.run_mode {
+            RunMode::Speciated => self.breed_species(),
+            RunMode::MapElites => {
+                self.breed_elites();
+                0
+            }
+        };
+
+        let evaluation_time = self.test_fitness(evaluator);
+        self.record_generation(
+            compatibility_threshold,
+            stagnated_species_removed,
+            evaluation_time,
+        );
+    }
+
+    /// Speciates the current genomes and replaces them with the offspring of the species,
+    /// returns how many stagnated species were removed
+    fn breed_species(&mut self) -> usize {
+        let generation = self.generation;
+        let stagnated_species_removed =
+            self.species_set
+                .speciate(&self.configuration, generation, &self.genomes);
+
+        let (elitism, population_size, mutation_rate, survival_ratio) = (
+            self.configuration.elitism,
+            self.configuration.population_size,
+            self.configuration.mutation_rate,
+            self.configuration.survival_ratio,
+        );
+        assert_ne!(self.species_set.species().len(), 0);
+        let (genomes, configuration, rng) = (&self.genomes, &self.configuration, &mut self.rng);
+        let shares: Vec<f64> = self
+            .species_set
+            .species()
+            .values()
+            .map(|species| species.adjusted_fitness.unwrap())
+            .collect();
+        let offspring_counts = largest_remainder(&shares, population_size as usize);
+        let offspring: Vec<(Genome, Option<MutationKind>)> = self
+            .species_set
+            .species()
+            .values()
+            .zip(offspring_counts)
+            .flat_map(|(species, offspring_count)| {
+                let mut member_ids_and_fitnesses: Vec<(GenomeId, f64)> = species
+                    .members
+                    .iter()
+                    .map(|member_id| (*member_id, genomes.get(*member_id).unwrap().score()))
+                    .collect();
+
+                member_ids_and_fitnesses.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+                // Pick survivors
+                let surviving_count: usize =
+                    (member_ids_and_fitnesses.len() as f64 * survival_ratio).ceil() as usize;
+                member_ids_and_fitnesses.truncate(surviving_count);
+
+                let elites_count: usize = usize::min(
+                    (offspring_count as f64 * elitism).ceil() as usize,
+                    usize::min(offspring_count, member_ids_and_fitnesses.len()),
+                );
+                let nonelites_count: usize = offspring_count - elites_count;
+
+                let elite_children: Vec<Genome> = (0..elites_count)
+                    .map(|elite_index| {
+                        let (elite_genome_id, _) =
+                            member_ids_and_fitnesses.get(elite_index).unwrap();
+                        genomes.get(*elite_genome_id).unwrap().clone()
+                    })
+                    .collect();
+
+                // Failed crossovers are retried with other parents, as a last resort
+                // the champion of the species is copied so no child goes missing
+                let crossover_children: Vec<Genome> = (0..nonelites_count)
+                    .map(|_| {
+                        for _ in 0..CROSSOVER_ATTEMPTS {
+                            let (parent_a_id, parent_a_fitness) =
+                                member_ids_and_fitnesses.rande(rng);
+                            let (parent_b_id, parent_b_fitness) =
+                                member_ids_and_fitnesses.rande(rng);
+
+                            let maybe_child = crossover(
+                                (genomes.get(*parent_a_id).unwrap(), *parent_a_fitness),
+                                (genomes.get(*parent_b_id).unwrap(), *parent_b_fitness),
+                                rng,
+                            );
+                            if let Some(child) = maybe_child {
+                                return child;
                             }
-                        });
+                        }
 
-                    elite_children
-                        .into_iter()
-                        .chain(crossover_children)
-                        .collect::<Vec<Gd<Genome>>>()
-                })
+                        let (champion_id, _) = member_ids_and_fitnesses[0];
+                        genomes.get(champion_id).unwrap().duplicate(rng)
+                    })
+                    .collect();
+
+                let mutations_for_children: Vec<Option<MutationKind>> = crossover_children
+                    .iter()
+                    .map(|_| {
+                        if rng.randf() < mutation_rate {
+                            Some(pick_mutation(configuration, rng))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+
+                elite_children
+                    .into_iter()
+                    .map(|elite| (elite, None))
+                    .chain(crossover_children.into_iter().zip(mutations_for_children))
+                    .collect::<Vec<(Genome, Option<MutationKind>)>>()
+            })
+            .collect();
+
+        self.genomes.clear();
+        assert_eq!(offspring.len(), population_size as usize);
+        // Mutations share the innovation tracker, so they are applied one by one
+        for (mut genome, maybe_mutation) in offspring {
+            if let Some(mutation) = maybe_mutation {
+                mutate(
+                    &mut genome,
+                    &mutation,
+                    &self.configuration,
+                    &mut self.innovations,
+                    &mut self.rng,
+                );
+            }
+            self.genomes.add_genome(genome);
+        }
+        stagnated_species_removed
+    }
+
+    /// Replaces the current genomes with mutated copies of elites sampled from the grid
+    fn breed_elites(&mut self) {
+        let population_size = self.configuration.population_size as usize;
+        let (configuration, innovations, rng) =
+            (&self.configuration, &mut self.innovations, &mut self.rng);
+        let elites: Vec<&Genome> = self.elite_map.cells().values().collect();
+        assert!(
+            !elites.is_empty(),
+            "MAP-Elites needs at least one evaluated genome in the grid"
+        );
+
+        let offspring: Vec<Genome> = (0..population_size)
+            .map(|_| {
+                let mut child = elites.rande(rng).duplicate(rng);
+                let mutation = pick_mutation(configuration, rng);
+                mutate(&mut child, &mutation, configuration, innovations, rng);
+                child
+            })
+            .collect();
+
+        self.genomes.clear();
+        offspring
+            .into_iter()
+            .for_each(|genome| self.genomes.add_genome(genome));
+    }
+
+    /// Whether the generation limit or the fitness goal has been reached
+    pub fn is_finished(&self) -> bool {
+        if self.generation >= self.configuration.max_generations {
+            return true;
+        }
+
+        if let Some(goal) = self.configuration.fitness_goal {
+            !self.genomes.genomes().is_empty() && self.get_best().1 >= goal
+        } else {
+            false
+        }
+    }
+
+    /// Runs the whole evolution process at once
+    pub fn start(&mut self, evaluator: &mut impl Evaluator) -> TrainingResult {
+        self.initialize(evaluator);
+        while !self.is_finished() {
+            self.step(evaluator);
+        }
+
+        let (network, fitness) = self.get_result();
+        TrainingResult {
+            network,
+            fitness,
+            pareto_front: self.pareto_front().into_iter().cloned().collect(),
+            elites: self.elite_networks(),
+        }
+    }
+
+    /// The best network ever seen and its fitness
+    ///
+    /// Comes from the hall of fame, or from the current generation when it's disabled.
+    pub fn get_result(&self) -> (Network, f64) {
+        if let Some(best) = self.hall_of_fame.best() {
+            return (self.network(best), best.fitness());
+        }
+
+        let (best_id, best_fitness) = self.get_best();
+        (
+            self.network(self.genomes.get(best_id).unwrap()),
+            best_fitness,
+        )
+    }
+
+    /// The network a genome encodes, with a substrate the genome is the CPPN painting it
+    pub fn network(&self, genome: &Genome) -> Network {
+        match &self.substrate {
+            Some(substrate) if self.configuration.es_hyperneat => {
+                substrate.build_evolvable(genome, &self.configuration)
+            }
+            Some(substrate) => substrate.build(
+                genome,
+                self.configuration.hyperneat_expression_threshold,
+                self.configuration.hyperneat_max_weight,
+            ),
+            None => Network::from_genome(genome),
+        }
+    }
+
+    /// The networks of every MAP-Elites elite, keyed by the coordinates of their cell
+    pub fn elite_networks(&self) -> BTreeMap<Vec<u32>, Network> {
+        self.elite_map
+            .cells()
+            .iter()
+            .map(|(cell, genome)| (cell.clone(), self.network(genome)))
+            .collect()
+    }
+
+    /// Evaluates the current genomes and scores them for selection, returns the seconds evaluation took
+    fn test_fitness(&mut self, evaluator: &mut impl Evaluator) -> f64 {
+        let (ids, mut networks): (Vec<GenomeId>, Vec<Network>) = self
+            .genomes
+            .genomes()
+            .iter()
+            .map(|(genome_id, genome)| (*genome_id, self.network(genome)))
+            .unzip();
+        let evaluation_start = Instant::now();
+        let evaluations = evaluator.evaluate_all(&mut networks);
+        let evaluation_time = evaluation_start.elapsed().as_secs_f64();
+        assert_eq!(
+            evaluations.len(),
+            ids.len(),
+            "the evaluator needs to return one fitness per network"
+        );
+
+        let (node_cost, connection_cost) = (
+            self.configuration.node_cost,
+            self.configuration.connection_cost,
+        );
+        for (genome_id, evaluation) in ids.iter().zip(evaluations) {
+            let genome = self.genomes.get_mut(*genome_id).unwrap();
+            let cost = node_cost * genome.nodes().len() as f64
+                + connection_cost * genome.connections().len() as f64;
+            genome.fitness = Some(evaluation.fitness - cost);
+            genome.behavior = evaluation.behavior;
+            genome.objectives = evaluation.objectives;
+            // Complexity competes with the other objectives instead of being subtracted from them
+            if !genome.objectives.is_empty() && cost > 0. {
+                genome.objectives.push(-cost);
+            }
+        }
+
+        let (bank, configuration, novelty_archive) = (
+            &self.genomes,
+            &self.configuration,
+            &mut self.novelty_archive,
+        );
+        let genomes: Vec<&Genome> = ids.iter().map(|id| bank.get(*id).unwrap()).collect();
+        let mut scores: Vec<Option<f64>> = vec![None; ids.len()];
+
+        if genomes.iter().any(|genome| !genome.objectives.is_empty()) {
+            let objectives: Vec<Vec<f64>> = genomes
+                .iter()
+                .map(|genome| genome.objectives.clone())
                 .collect();
+            scores = pareto::scores(&objectives).into_iter().map(Some).collect();
+        }
 
-            self.genomes.bind_mut().clear();
-            assert_ne!(offspring.len(), 0);
-            offspring
+        let novelty_weight = configuration.novelty_weight;
+        if novelty_weight > 0. {
+            let behaviors: Vec<Vec<f64>> = genomes
+                .iter()
+                .map(|genome| genome.behavior.clone())
+                .collect();
+            let novelties = novelty_archive.evaluate(
+                &behaviors,
+                configuration.novelty_neighbors as usize,
+                configuration.novelty_archive_threshold,
+            );
+            scores = scores
                 .into_iter()
-                .for_each(|genome| self.genomes.bind_mut().add_genome(genome));
-            self.test_fitness();
-            self.reporter_fn.callv(varray![i]);
-            let goal_reached = {
-                if let Some(goal) = self.configuration.bind().fitness_goal {
-                    self.get_best().bind().best_fitness >= goal
-                } else {
-                    false
-                }
-            };
+                .zip(&genomes)
+                .zip(novelties)
+                .map(|((score, genome), novelty)| {
+                    let score = score.unwrap_or_else(|| genome.fitness());
+                    Some((1. - novelty_weight) * score + novelty_weight * novelty)
+                })
+                .collect();
+        }
+
+        for (genome_id, score) in ids.into_iter().zip(scores) {
+            self.genomes.get_mut(genome_id).unwrap().score = score;
+        }
+
+        evaluation_time
+    }
 
-            if goal_reached {
-                break;
+    fn record_generation(
+        &mut self,
+        compatibility_threshold: f64,
+        stagnated_species_removed: usize,
+        evaluation_time: f64,
+    ) {
+        self.hall_of_fame.update(
+            self.genomes.genomes().values(),
+            self.configuration.hall_of_fame_size as usize,
+        );
+
+        if self.configuration.run_mode == RunMode::MapElites {
+            for genome in self.genomes.genomes().values() {
+                self.elite_map
+                    .insert(&self.configuration.map_elites_dimensions, genome);
             }
         }
 
-        let best = self.get_best();
-        let res = Gd::new(StartResult {
-            network: Network::from_genome(
-                &self
-                    .genomes
-                    .bind()
-                    .get(best.bind().best_id.into())
-                    .unwrap()
-                    .bind(),
-            ),
-            best_fitness: best.bind().best_fitness,
-        });
-        res
+        self.statistics.push(GenerationStatistics::collect(
+            self.generation,
+            &self.genomes,
+            &self.species_set,
+            compatibility_threshold,
+            stagnated_species_removed,
+            evaluation_time,
+        ));
     }
 
-    fn test_fitness(&mut self) {
-        let id_and_fitness: Vec<(GenomeId, f64)> = self
+    /// Genomes of the current generation no other genome beats in every objective,
+    /// empty unless the evaluator returned objectives
+    pub fn pareto_front(&self) -> Vec<&Genome> {
+        let genomes: Vec<&Genome> = self
             .genomes
-            .bind()
             .genomes()
+            .values()
+            .filter(|genome| !genome.objectives.is_empty())
+            .collect();
+        let objectives: Vec<Vec<f64>> = genomes
             .iter()
-            .map(|(genome_id, genome)| {
-                bind!(genome);
-                let net = Network::from_genome(&genome);
-                let mut fitness = f64::from_variant(&self.fitness_fn.callv(varray![net]));
-
-                fitness -= self.configuration.bind().node_cost * genome.nodes().len() as f64;
-                fitness -=
-                    self.configuration.bind().connection_cost * genome.connections().len() as f64;
-                (genome_id, fitness)
-            })
+            .map(|genome| genome.objectives.clone())
             .collect();
-        id_and_fitness
+
+        pareto::non_dominated_sort(&objectives)
+            .into_iter()
+            .next()
+            .unwrap_or_default()
             .into_iter()
-            .for_each(|(genome_id, genome_fitness)| {
-                self.genomes
-                    .bind_mut()
-                    .mark_fitness(genome_id, genome_fitness);
-            });
+            .map(|i| genomes[i])
+            .collect()
     }
 
-    #[func]
-    pub fn get_best(&self) -> Gd<BestResult> {
-        assert!(!self.genomes.bind().genomes().is_empty());
-        let (best_id, best_fit) = self
-            .genomes
-            .bind()
+    /// Id and fitness of the best genome in the current generation
+    pub fn get_best(&self) -> (GenomeId, f64) {
+        assert!(!self.genomes.genomes().is_empty());
+        self.genomes
             .genomes()
             .iter()
-            .map(|(gid, g)| (gid, g.bind().fitness.unwrap()))
+            .map(|(gid, g)| (*gid, g.fitness.unwrap()))
             .fold(
-                (GenomeId::default(), f64::MIN),
-                |(best_id, best_fitness), (genome_id, genome_fitness)| {
-                    if genome_fitness > best_fitness {
-                        (genome_id, genome_fitness)
-                    } else {
-                        (best_id, best_fitness)
-                    }
+                None,
+                |best: Option<(GenomeId, f64)>, (genome_id, genome_fitness)| match best {
+                    Some((_, best_fitness)) if genome_fitness <= best_fitness => best,
+                    _ => Some((genome_id, genome_fitness)),
                 },
+            )
+            .unwrap()
+    }
+}
+
+/// How many times parents are picked for a child before giving up on crossover
+const CROSSOVER_ATTEMPTS: usize = 10;
+
+/// Splits `total` proportionally to `shares` with the largest remainder method,
+/// the counts always add up to `total`
+fn largest_remainder(shares: &[f64], total: usize) -> Vec<usize> {
+    let share_sum: f64 = shares.iter().sum();
+    let quotas: Vec<f64> = shares
+        .iter()
+        .map(|share| share / share_sum * total as f64)
+        .collect();
+
+    let mut counts: Vec<usize> = quotas.iter().map(|quota| quota.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..quotas.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        let remainder = |i: usize| quotas[i] - quotas[i].floor();
+        remainder(*b).total_cmp(&remainder(*a))
+    });
+
+    let missing = total - counts.iter().sum::<usize>();
+    by_remainder
+        .into_iter()
+        .cycle()
+        .take(missing)
+        .for_each(|i| counts[i] += 1);
+
+    counts
+}
+
+/// A genome of the initial population, connected as `initial_topology` says
+fn initial_genome(
+    inputs: u32,
+    outputs: u32,
+    configuration: &Configuration,
+    innovations: &mut InnovationTracker,
+    rng: &mut Rng,
+) -> Genome {
+    let recurrent = configuration.allow_recurrent;
+    if configuration.initial_topology == InitialTopology::FullyConnected && !configuration.bias_node
+    {
+        return Genome::new(inputs, outputs, recurrent, innovations, rng);
+    }
+
+    let mut genome = Genome::unconnected(inputs, outputs, recurrent, innovations, rng);
+    // The bias node is wired up like one more input
+    let mut input_ids: Vec<u32> = (0..inputs).collect();
+    if configuration.bias_node {
+        input_ids.push(genome.add_bias_node(innovations, rng));
+    }
+    let output_ids: Vec<u32> = (inputs..inputs + outputs).collect();
+    // The id right after the outputs is kept for the bias node even when there is none
+    let first_hidden = inputs + outputs + 1;
+    let connect = |genome: &mut Genome,
+                   from: u32,
+                   to: u32,
+                   innovations: &mut InnovationTracker,
+                   rng: &mut Rng| {
+        genome.add_connection(from, to, innovations.connection(from, to), rng);
+    };
+
+    match configuration.initial_topology {
+        InitialTopology::FullyConnected => {
+            for from in &input_ids {
+                for to in &output_ids {
+                    connect(&mut genome, *from, *to, innovations, rng);
+                }
+            }
+        }
+        InitialTopology::SingleInput if inputs > 0 && outputs > 0 => {
+            let (from, to) = (*input_ids.rande(rng), *output_ids.rande(rng));
+            connect(&mut genome, from, to, innovations, rng);
+        }
+        InitialTopology::RandomFraction => {
+            for from in &input_ids {
+                for to in &output_ids {
+                    if rng.randf() < configuration.initial_connection_fraction {
+                        connect(&mut genome, *from, *to, innovations, rng);
+                    }
+                }
+            }
+        }
+        InitialTopology::OneHiddenLayer => {
+            // Hidden nodes get the same ids in every genome so they line up in crossover
+            let hidden_ids: Vec<u32> = (0..configuration.initial_hidden_nodes)
+                .map(|i| first_hidden + i)
+                .collect();
+            innovations.reserve_nodes(first_hidden + configuration.initial_hidden_nodes);
+            for hidden in &hidden_ids {
+                genome.add_node(*hidden, rng);
+            }
+            for hidden in &hidden_ids {
+                for from in &input_ids {
+                    connect(&mut genome, *from, *hidden, innovations, rng);
+                }
+                for to in &output_ids {
+                    connect(&mut genome, *hidden, *to, innovations, rng);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    genome
+}
+
+fn pick_mutation(configuration: &Configuration, rng: &mut Rng) -> MutationKind {
+    // Without node biases there are no biases to modify
+    let kinds: Vec<MutationKind> = configuration
+        .mutation_kinds
+        .iter()
+        .map(|(kind, _)| *kind)
+        .filter(|kind| configuration.node_biases || *kind != MutationKind::ModifyBias)
+        .collect();
+    assert!(
+        !kinds.is_empty(),
+        "mutation_kinds needs a mutation other than ModifyBias when node_biases is off"
+    );
+    *kinds.rande(rng)
+}
+
+/// Mutates a genome, keeping its node biases at 0 when they are disabled
+fn mutate(
+    genome: &mut Genome,
+    kind: &MutationKind,
+    configuration: &Configuration,
+    innovations: &mut InnovationTracker,
+    rng: &mut Rng,
+) {
+    mutations::mutate(kind, genome, innovations, rng);
+    if !configuration.node_biases {
+        genome.clear_biases();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fitness on XOR, 1 for a perfect network
+    pub(super) fn xor(network: &mut Network) -> f64 {
+        let error: f64 = [(0., 0., 0.), (0., 1., 1.), (1., 0., 1.), (1., 1., 0.)]
+            .iter()
+            .map(|(a, b, expected)| (expected - network.forward_pass(&[*a, *b])[0]).powi(2))
+            .sum();
+        1. / (1. + error)
+    }
+
+    fn neat(seed: u64) -> NEAT {
+        let mut neat = NEAT::new(2, 1);
+        neat.set_seed(seed);
+        neat.configuration.population_size = 30;
+        neat.configuration.max_generations = 5;
+        neat
+    }
+
+    #[test]
+    fn largest_remainder_adds_up_to_the_total() {
+        assert_eq!(largest_remainder(&[1., 1., 1.], 10), vec![4, 3, 3]);
+        assert_eq!(largest_remainder(&[0.5, 0.3, 0.2], 10), vec![5, 3, 2]);
+        assert_eq!(largest_remainder(&[0.15, 0.15, 0.7], 10), vec![2, 1, 7]);
+        assert_eq!(largest_remainder(&[2., 0.], 7), vec![7, 0]);
+    }
+
+    fn initial(topology: InitialTopology, configure: impl Fn(&mut Configuration)) -> Genome {
+        let mut configuration = Configuration {
+            initial_topology: topology,
+            ..Configuration::default()
+        };
+        configure(&mut configuration);
+        initial_genome(
+            2,
+            1,
+            &configuration,
+            &mut InnovationTracker::new(),
+            &mut Rng::new(0),
+        )
+    }
+
+    fn links(genome: &Genome) -> Vec<(u32, u32)> {
+        let mut links: Vec<(u32, u32)> = genome
+            .connections()
+            .iter()
+            .map(|connection| (connection.from, connection.to))
+            .collect();
+        links.sort_unstable();
+        links
+    }
+
+    #[test]
+    fn initial_genomes_follow_the_initial_topology() {
+        let fully_connected = initial(InitialTopology::FullyConnected, |_| {});
+        assert_eq!(fully_connected.nodes().len(), 3);
+        assert_eq!(links(&fully_connected), vec![(0, 2), (1, 2)]);
+
+        let unconnected = initial(InitialTopology::Unconnected, |_| {});
+        assert_eq!(unconnected.nodes().len(), 3);
+        assert!(unconnected.connections().is_empty());
+
+        let single_input = initial(InitialTopology::SingleInput, |_| {});
+        assert_eq!(single_input.nodes().len(), 3);
+        let single_links = links(&single_input);
+        assert!(single_links == vec![(0, 2)] || single_links == vec![(1, 2)]);
+
+        let all = initial(InitialTopology::RandomFraction, |c| {
+            c.initial_connection_fraction = 1.
+        });
+        assert_eq!(links(&all), vec![(0, 2), (1, 2)]);
+        let none = initial(InitialTopology::RandomFraction, |c| {
+            c.initial_connection_fraction = 0.
+        });
+        assert!(none.connections().is_empty());
+    }
+
+    #[test]
+    fn initial_hidden_nodes_come_after_the_bias_node_id() {
+        let hidden_layer = initial(InitialTopology::OneHiddenLayer, |c| {
+            c.initial_hidden_nodes = 2
+        });
+        assert_eq!(hidden_layer.nodes().len(), 5);
+        assert_eq!(hidden_layer.bias_node(), None);
+        assert_eq!(
+            links(&hidden_layer),
+            vec![(0, 4), (0, 5), (1, 4), (1, 5), (4, 2), (5, 2)]
+        );
+
+        let with_bias = initial(InitialTopology::OneHiddenLayer, |c| {
+            c.initial_hidden_nodes = 2;
+            c.bias_node = true;
+        });
+        assert_eq!(with_bias.nodes().len(), 6);
+        assert_eq!(with_bias.bias_node(), Some(3));
+        assert_eq!(
+            links(&with_bias),
+            vec![
+                (0, 4),
+                (0, 5),
+                (1, 4),
+                (1, 5),
+                (3, 4),
+                (3, 5),
+                (4, 2),
+                (5, 2)
+            ]
+        );
+
+        let fully_connected = initial(InitialTopology::FullyConnected, |c| c.bias_node = true);
+        assert_eq!(links(&fully_connected), vec![(0, 2), (1, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn unconnected_genomes_are_not_all_apart() {
+        let mut neat = neat(1);
+        neat.configuration.initial_topology = InitialTopology::Unconnected;
+        neat.initialize(&mut xor);
+        neat.step(&mut xor);
+
+        assert!(neat.species_set.species().len() < 30);
+    }
+
+    #[test]
+    fn initialize_evaluates_the_initial_population() {
+        let mut neat = neat(1);
+        neat.initialize(&mut xor);
+
+        assert_eq!(neat.generation(), 0);
+        assert_eq!(neat.genomes.genomes().len(), 30);
+        assert!(neat.genomes.genomes().values().all(|g| g.fitness.is_some()));
+        assert_eq!(neat.statistics.generations().len(), 1);
+    }
+
+    #[test]
+    fn step_keeps_the_population_size() {
+        let mut neat = neat(1);
+        neat.initialize(&mut xor);
+        for generation in 1..=3 {
+            neat.step(&mut xor);
+
+            assert_eq!(neat.generation(), generation);
+            assert_eq!(neat.genomes.genomes().len(), 30);
+            assert!(neat.genomes.genomes().values().all(|g| g.fitness.is_some()));
+        }
+        assert_eq!(neat.statistics.generations().len(), 4);
+    }
+
+    #[test]
+    fn statistics_record_the_threshold_species_were_formed_with() {
+        let mut neat = neat(1);
+        neat.configuration.target_species_count = 1000;
+        neat.configuration.compatibility_threshold_step = 0.5;
+        neat.initialize(&mut xor);
+        neat.step(&mut xor);
+        neat.step(&mut xor);
+
+        let thresholds: Vec<f64> = neat
+            .statistics
+            .generations()
+            .iter()
+            .map(|generation| generation.compatibility_threshold)
+            .collect();
+        assert_eq!(thresholds, vec![3., 3., 2.5]);
+        assert_eq!(
+            neat.species_set
+                .compatibility_threshold(&neat.configuration),
+            2.
+        );
+    }
+
+    #[test]
+    fn batch_fitnesses_go_to_the_genomes_in_order() {
+        let mut neat = neat(1);
+        let mut batch = Batch(|networks: &mut [Network]| {
+            networks
+                .iter()
+                .map(|network| network.connections.len() as f64)
+                .collect()
+        });
+        neat.initialize(&mut batch);
+        for _ in 0..3 {
+            neat.step(&mut batch);
+        }
+
+        let genomes = neat.genomes.genomes();
+        for genome in genomes.values() {
+            let connections = Network::from_genome(genome).connections.len();
+            assert_eq!(genome.fitness, Some(connections as f64));
+        }
+        // Otherwise the order wouldn't matter
+        assert!(genomes
+            .values()
+            .any(|g| g.fitness != genomes.values().next().unwrap().fitness));
+    }
+
+    #[test]
+    fn bias_mutations_are_skipped_without_node_biases() {
+        let configuration = Configuration {
+            mutation_kinds: vec![(MutationKind::ModifyBias, 1), (MutationKind::AddNode, 1)],
+            node_biases: false,
+            ..Configuration::default()
+        };
+        let mut rng = Rng::new(0);
+
+        for _ in 0..20 {
+            assert_eq!(
+                pick_mutation(&configuration, &mut rng),
+                MutationKind::AddNode
             );
-        // println!("{:#?}", self.genomes.genomes().len()); the problem is it goes to 0 at the end sometimes
-        Gd::new(BestResult {
-            best_id: best_id.into(),
-            best_fitness: best_fit,
-        })
+        }
     }
 
-    fn pick_mutation(&self) -> MutationKind {
-        self.configuration.bind().mutation_kinds.rande().0
+    #[test]
+    #[should_panic(expected = "mutation other than ModifyBias")]
+    fn only_bias_mutations_without_node_biases_are_rejected() {
+        let configuration = Configuration {
+            mutation_kinds: vec![(MutationKind::ModifyBias, 1)],
+            node_biases: false,
+            ..Configuration::default()
+        };
+        pick_mutation(&configuration, &mut Rng::new(0));
+    }
+
+    #[test]
+    fn run_stops_at_max_generations() {
+        let mut neat = neat(1);
+        neat.initialize(&mut xor);
+        while !neat.is_finished() {
+            neat.step(&mut xor);
+        }
+
+        assert_eq!(neat.generation(), 5);
+    }
+
+    #[test]
+    fn start_returns_the_pareto_front_of_the_final_generation() {
+        let mut neat = neat(1);
+        let result = neat.start(&mut Objectives(|network: &mut Network| {
+            let (a, b) = (
+                network.forward_pass(&[1., 0.])[0],
+                network.forward_pass(&[0., 1.])[0],
+            );
+            vec![a, b - a]
+        }));
+
+        assert!(!result.pareto_front.is_empty());
+        assert_eq!(
+            result.pareto_front,
+            neat.pareto_front()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<Genome>>()
+        );
+        for member in &result.pareto_front {
+            assert!(neat.genomes.genomes().values().all(|genome| {
+                let better = genome.objectives.iter().zip(&member.objectives);
+                !(better.clone().all(|(g, m)| g >= m) && better.clone().any(|(g, m)| g > m))
+            }));
+        }
+    }
+
+    #[test]
+    fn start_returns_the_networks_of_the_elite_archive() {
+        let mut neat = neat(1);
+        neat.configuration.run_mode = RunMode::MapElites;
+        neat.configuration.map_elites_dimensions = vec![GridDimension::new(0., 1., 4)];
+        let result = neat.start(&mut Behavior(|network: &mut Network| {
+            let output = network.forward_pass(&[1., 0.])[0];
+            (xor(network), vec![output])
+        }));
+
+        assert!(!result.elites.is_empty());
+        assert_eq!(
+            result.elites.keys().collect::<Vec<_>>(),
+            neat.elite_map.cells().keys().collect::<Vec<_>>()
+        );
+        for (cell, genome) in neat.elite_map.cells() {
+            assert_eq!(
+                result.elites[cell].to_bytes(),
+                Network::from_genome(genome).to_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_run() {
+        let (mut a, mut b) = (neat(3), neat(3));
+        let (result_a, result_b) = (a.start(&mut xor), b.start(&mut xor));
+
+        assert_eq!(result_a.fitness, result_b.fitness);
+        assert_eq!(result_a.network.to_bytes(), result_b.network.to_bytes());
     }
 }
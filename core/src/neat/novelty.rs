@@ -0,0 +1,116 @@
+use nanoserde::{DeBin, SerBin};
+
+/// Behaviors that were novel when they were first seen, novelty search compares against them
+#[derive(Debug, Clone, Default, DeBin, SerBin)]
+pub struct NoveltyArchive {
+    behaviors: Vec<Vec<f64>>,
+}
+
+impl NoveltyArchive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn behaviors(&self) -> &[Vec<f64>] {
+        &self.behaviors
+    }
+
+    /// Scores every behavior of a generation by its sparseness, the mean distance to the
+    /// `neighbors` closest behaviors of the generation and the archive, then archives
+    /// the behaviors scoring at least `threshold`
+    pub fn evaluate(
+        &mut self,
+        behaviors: &[Vec<f64>],
+        neighbors: usize,
+        threshold: f64,
+    ) -> Vec<f64> {
+        let novelties: Vec<f64> = behaviors
+            .iter()
+            .enumerate()
+            .map(|(i, behavior)| {
+                let mut distances: Vec<f64> = behaviors
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| other)
+                    .chain(self.behaviors.iter())
+                    .map(|other| distance(behavior, other))
+                    .collect();
+                distances.sort_by(|a, b| a.total_cmp(b));
+                distances.truncate(neighbors);
+
+                if distances.is_empty() {
+                    0.
+                } else {
+                    distances.iter().sum::<f64>() / distances.len() as f64
+                }
+            })
+            .collect();
+
+        behaviors
+            .iter()
+            .zip(&novelties)
+            .filter(|(_, novelty)| **novelty >= threshold)
+            .for_each(|(behavior, _)| self.behaviors.push(behavior.clone()));
+
+        novelties
+    }
+}
+
+/// Euclidean distance, missing components count as 0
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    (0..usize::max(a.len(), b.len()))
+        .map(|i| {
+            let difference = a.get(i).unwrap_or(&0.) - b.get(i).unwrap_or(&0.);
+            difference * difference
+        })
+        .sum::<f64>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(archive: &mut NoveltyArchive, behaviors: &[f64], neighbors: usize) -> Vec<f64> {
+        let behaviors: Vec<Vec<f64>> = behaviors.iter().map(|b| vec![*b]).collect();
+        archive.evaluate(&behaviors, neighbors, f64::INFINITY)
+    }
+
+    #[test]
+    fn missing_components_count_as_zero() {
+        assert_eq!(distance(&[0., 0.], &[3., 4.]), 5.);
+        assert_eq!(distance(&[3.], &[0., 4.]), 5.);
+        assert_eq!(distance(&[], &[]), 0.);
+    }
+
+    #[test]
+    fn novelty_is_the_mean_distance_to_the_nearest_neighbors() {
+        let mut archive = NoveltyArchive::new();
+
+        assert_eq!(evaluate(&mut archive, &[0., 1., 3.], 1), vec![1., 1., 2.]);
+        assert_eq!(evaluate(&mut archive, &[0., 1., 3.], 2), vec![2., 1.5, 2.5]);
+        assert!(archive.behaviors().is_empty());
+    }
+
+    #[test]
+    fn behaviors_at_the_threshold_are_archived() {
+        let mut archive = NoveltyArchive::new();
+        let behaviors = vec![vec![0.], vec![1.], vec![3.]];
+
+        assert_eq!(archive.evaluate(&behaviors, 1, 2.), vec![1., 1., 2.]);
+        assert_eq!(archive.behaviors(), &[vec![3.]]);
+        // Archived behaviors are neighbors of the next generations
+        assert_eq!(evaluate(&mut archive, &[3.5], 1), vec![0.5]);
+    }
+
+    #[test]
+    fn fewer_behaviors_than_neighbors_use_all_of_them() {
+        let mut archive = NoveltyArchive::new();
+        archive.evaluate(&[vec![5.]], 1, 0.);
+        assert_eq!(archive.behaviors().len(), 1);
+
+        assert_eq!(evaluate(&mut archive, &[0., 2.], 10), vec![3.5, 2.5]);
+        assert_eq!(evaluate(&mut NoveltyArchive::new(), &[1.], 10), vec![0.]);
+    }
+}
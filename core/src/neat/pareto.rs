@@ -0,0 +1,171 @@
+//! NSGA-II style ranking of genomes evaluated on several objectives, all of which are maximized
+
+use std::cmp::Ordering;
+
+/// Whether `a` is at least as good as `b` in every objective and better in at least one
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    let mut better = false;
+    for (a, b) in a.iter().zip(b) {
+        match a.total_cmp(b) {
+            Ordering::Less => return false,
+            Ordering::Greater => better = true,
+            Ordering::Equal => {}
+        }
+    }
+    better
+}
+
+/// Groups indexes of `objectives` into fronts, the first front is the Pareto front
+/// and every later one is only dominated by the fronts before it
+pub fn non_dominated_sort(objectives: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    let count = objectives.len();
+    let mut dominated_by_count = vec![0; count];
+    let mut dominating: Vec<Vec<usize>> = vec![vec![]; count];
+
+    for i in 0..count {
+        for j in 0..count {
+            if dominates(&objectives[i], &objectives[j]) {
+                dominating[i].push(j);
+            } else if dominates(&objectives[j], &objectives[i]) {
+                dominated_by_count[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = vec![];
+    let mut front: Vec<usize> = (0..count).filter(|i| dominated_by_count[*i] == 0).collect();
+    while !front.is_empty() {
+        let mut next_front = vec![];
+        for i in &front {
+            for j in &dominating[*i] {
+                dominated_by_count[*j] -= 1;
+                if dominated_by_count[*j] == 0 {
+                    next_front.push(*j);
+                }
+            }
+        }
+        next_front.sort_unstable();
+
+        fronts.push(front);
+        front = next_front;
+    }
+
+    fronts
+}
+
+/// How isolated every member of a front is from its neighbors in objective space,
+/// the extremes of every objective are infinitely isolated
+pub fn crowding_distances(objectives: &[Vec<f64>], front: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.; front.len()];
+    let objective_count = front
+        .iter()
+        .map(|i| objectives[*i].len())
+        .min()
+        .unwrap_or(0);
+
+    // The values of one objective across the front at a time
+    let columns = (0..objective_count)
+        .map(|objective| -> Vec<f64> { front.iter().map(|i| objectives[*i][objective]).collect() });
+    for values in columns {
+        let value = |position: usize| values[position];
+
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|a, b| value(*a).total_cmp(&value(*b)));
+
+        let (first, last) = (order[0], order[order.len() - 1]);
+        let range = value(last) - value(first);
+        distances[first] = f64::INFINITY;
+        distances[last] = f64::INFINITY;
+        // Identical or unbounded values say nothing about crowding
+        if !(range > 0. && range.is_finite()) {
+            continue;
+        }
+
+        for window in order.windows(3) {
+            distances[window[1]] += (value(window[2]) - value(window[0])) / range;
+        }
+    }
+
+    distances
+}
+
+/// Turns fronts and crowding distances into one score per genome, a better front always
+/// scores higher and within a front less crowded genomes score higher
+pub fn scores(objectives: &[Vec<f64>]) -> Vec<f64> {
+    let mut scores = vec![0.; objectives.len()];
+
+    for (rank, front) in non_dominated_sort(objectives).iter().enumerate() {
+        for (i, distance) in front.iter().zip(crowding_distances(objectives, front)) {
+            // Maps the distance into [0, 0.5] so it never outweighs the rank
+            let crowding = if distance.is_infinite() {
+                0.5
+            } else {
+                distance / (1. + distance) * 0.5
+            };
+            scores[*i] = -(rank as f64) + crowding;
+        }
+    }
+
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn objectives(values: &[[f64; 2]]) -> Vec<Vec<f64>> {
+        values.iter().map(|v| v.to_vec()).collect()
+    }
+
+    #[test]
+    fn dominance_needs_one_strictly_better_objective() {
+        assert!(dominates(&[2., 1.], &[1., 1.]));
+        assert!(!dominates(&[1., 1.], &[1., 1.]));
+        assert!(!dominates(&[2., 0.], &[1., 1.]));
+    }
+
+    #[test]
+    fn fronts_are_sorted_by_dominance() {
+        let objectives = objectives(&[[1., 1.], [3., 1.], [1., 3.], [2., 2.], [0., 0.]]);
+
+        assert_eq!(
+            non_dominated_sort(&objectives),
+            vec![vec![1, 2, 3], vec![0], vec![4]]
+        );
+    }
+
+    #[test]
+    fn crowding_distance_favors_the_extremes_and_isolated_members() {
+        let objectives = objectives(&[[0., 4.], [1., 3.], [3., 1.], [4., 0.]]);
+        let distances = crowding_distances(&objectives, &[0, 1, 2, 3]);
+
+        assert!(distances[0].is_infinite() && distances[3].is_infinite());
+        assert_eq!(distances[1], distances[2]);
+        assert_eq!(distances[1], 1.5);
+    }
+
+    #[test]
+    fn scores_rank_fronts_before_crowding() {
+        let objectives = objectives(&[[1., 1.], [3., 1.], [1., 3.], [2., 2.], [0., 0.]]);
+        let scores = scores(&objectives);
+
+        // The whole first front beats the dominated genomes
+        let worst_of_front = scores[1].min(scores[2]).min(scores[3]);
+        assert!(worst_of_front > scores[0]);
+        assert!(scores[0] > scores[4]);
+        // Extremes of the front are the least crowded
+        assert!(scores[1] > scores[3] && scores[2] > scores[3]);
+        assert!(scores.iter().all(|score| score.is_finite()));
+    }
+
+    #[test]
+    fn scores_survive_unbounded_objectives() {
+        let objectives = vec![
+            vec![f64::INFINITY, 0.],
+            vec![0., 1.],
+            vec![f64::NEG_INFINITY, 2.],
+        ];
+
+        assert!(scores(&objectives).iter().all(|score| !score.is_nan()));
+    }
+}
@@ -1,33 +1,28 @@
-use godot::prelude::*;
-
 use crate::genome::{Genome, GenomeId, GenomeMap};
 
-/// Holds all genomes and species, does the process of speciation
-#[derive(Debug, Default, GodotClass)]
-#[class(base=RefCounted)]
+/// Holds the genomes of the current and the previous generation
+#[derive(Debug, Default, Clone)]
 pub struct GenomeBank {
     genomes: GenomeMap,
     previous_genomes: GenomeMap,
 }
 
-#[godot_api]
-impl RefCountedVirtual for GenomeBank {
-    fn init(_base: Base<RefCounted>) -> Self {
+impl GenomeBank {
+    pub fn new() -> Self {
         Self::default()
     }
-}
 
-#[godot_api]
-impl GenomeBank {
-    #[func]
-    pub fn get_genomes(&self) -> Dictionary {
-        self.genomes.d()
+    /// A bank holding both generations, the previous one still provides the species representatives
+    pub fn from_genomes(genomes: GenomeMap, previous_genomes: GenomeMap) -> Self {
+        GenomeBank {
+            genomes,
+            previous_genomes,
+        }
     }
 
     /// Adds a new genome
-    pub fn add_genome(&mut self, genome: Gd<Genome>) {
-        let id = genome.bind().id();
-        self.genomes.set(id, genome);
+    pub fn add_genome(&mut self, genome: Genome) {
+        self.genomes.insert(genome.id(), genome);
     }
 
     /// Clear genomes
@@ -35,8 +30,19 @@ impl GenomeBank {
         self.previous_genomes = std::mem::take(&mut self.genomes);
     }
 
-    pub fn get(&self, genome_id: GenomeId) -> Option<Gd<Genome>> {
-        self.genomes.get(genome_id)
+    pub fn get(&self, genome_id: GenomeId) -> Option<&Genome> {
+        self.genomes.get(&genome_id)
+    }
+
+    pub fn get_mut(&mut self, genome_id: GenomeId) -> Option<&mut Genome> {
+        self.genomes.get_mut(&genome_id)
+    }
+
+    /// Looks in the current generation first, then in the previous one
+    pub fn find(&self, genome_id: GenomeId) -> Option<&Genome> {
+        self.genomes
+            .get(&genome_id)
+            .or_else(|| self.previous_genomes.get(&genome_id))
     }
 
     /// Returns a reference to the genomes
@@ -50,7 +56,7 @@ impl GenomeBank {
 
     /// Tracks the fitness of a particular genome
     pub fn mark_fitness(&mut self, genome_id: GenomeId, fitness: f64) -> Option<()> {
-        self.get(genome_id)?.bind_mut().fitness = Some(fitness);
+        self.get_mut(genome_id)?.fitness = Some(fitness);
         Some(())
     }
 }
@@ -0,0 +1,199 @@
+use nanoserde::{DeBin, SerBin, SerJson};
+
+use super::GenomeBank;
+use crate::speciation::SpeciesSet;
+
+/// What a single generation looked like
+#[derive(Debug, Clone, Default, DeBin, SerBin, SerJson)]
+pub struct GenerationStatistics {
+    pub generation: u32,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+    pub median_fitness: f64,
+    pub stddev_fitness: f64,
+    /// Species after this generation's speciation, none for the initial population
+    pub species_count: u32,
+    pub species_sizes: Vec<u32>,
+    /// The threshold this generation's species were formed with, it moves when targeting a species count
+    pub compatibility_threshold: f64,
+    pub mean_nodes: f64,
+    /// Disabled connection genes included
+    pub mean_connections: f64,
+    pub stagnated_species_removed: u32,
+    /// Seconds spent evaluating fitness
+    pub evaluation_time: f64,
+}
+
+impl GenerationStatistics {
+    pub(crate) fn collect(
+        generation: u32,
+        genomes: &GenomeBank,
+        species_set: &SpeciesSet,
+        compatibility_threshold: f64,
+        stagnated_species_removed: usize,
+        evaluation_time: f64,
+    ) -> Self {
+        let genomes = genomes.genomes();
+        let count = genomes.len() as f64;
+
+        let mut fitnesses: Vec<f64> = genomes
+            .values()
+            .map(|genome| genome.fitness.unwrap())
+            .collect();
+        fitnesses.sort_by(|a, b| a.total_cmp(b));
+
+        let mean_fitness = fitnesses.iter().sum::<f64>() / count;
+        let median_fitness = if fitnesses.len().is_multiple_of(2) {
+            (fitnesses[fitnesses.len() / 2 - 1] + fitnesses[fitnesses.len() / 2]) / 2.
+        } else {
+            fitnesses[fitnesses.len() / 2]
+        };
+        let variance = fitnesses
+            .iter()
+            .map(|fitness| (fitness - mean_fitness).powi(2))
+            .sum::<f64>()
+            / count;
+
+        GenerationStatistics {
+            generation,
+            best_fitness: fitnesses.last().copied().unwrap_or(f64::MIN),
+            mean_fitness,
+            median_fitness,
+            stddev_fitness: variance.sqrt(),
+            species_count: species_set.species().len() as u32,
+            species_sizes: species_set
+                .species()
+                .values()
+                .map(|species| species.members.len() as u32)
+                .collect(),
+            compatibility_threshold,
+            mean_nodes: genomes
+                .values()
+                .map(|genome| genome.nodes().len())
+                .sum::<usize>() as f64
+                / count,
+            mean_connections: genomes
+                .values()
+                .map(|genome| genome.connections().len())
+                .sum::<usize>() as f64
+                / count,
+            stagnated_species_removed: stagnated_species_removed as u32,
+            evaluation_time,
+        }
+    }
+}
+
+/// Statistics of every generation of a run
+#[derive(Debug, Clone, Default, DeBin, SerBin)]
+pub struct Statistics {
+    generations: Vec<GenerationStatistics>,
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generations(&self) -> &[GenerationStatistics] {
+        &self.generations
+    }
+
+    /// Statistics of the most recent generation
+    pub fn last(&self) -> Option<&GenerationStatistics> {
+        self.generations.last()
+    }
+
+    pub(crate) fn push(&mut self, generation: GenerationStatistics) {
+        self.generations.push(generation);
+    }
+
+    /// One row per generation, species sizes are separated by `;`
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "generation,best_fitness,mean_fitness,median_fitness,stddev_fitness,species_count,\
+             species_sizes,compatibility_threshold,mean_nodes,mean_connections,stagnated_species_removed,evaluation_time\n",
+        );
+
+        for g in &self.generations {
+            let species_sizes: Vec<String> = g
+                .species_sizes
+                .iter()
+                .map(|size| size.to_string())
+                .collect();
+            csv += &format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                g.generation,
+                g.best_fitness,
+                g.mean_fitness,
+                g.median_fitness,
+                g.stddev_fitness,
+                g.species_count,
+                species_sizes.join(";"),
+                g.compatibility_threshold,
+                g.mean_nodes,
+                g.mean_connections,
+                g.stagnated_species_removed,
+                g.evaluation_time,
+            );
+        }
+
+        csv
+    }
+
+    /// An array with one object per generation
+    pub fn to_json(&self) -> String {
+        self.generations.serialize_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::{Genome, InnovationTracker};
+    use crate::random::Rng;
+
+    fn bank(fitnesses: &[f64]) -> GenomeBank {
+        let (mut innovations, mut rng) = (InnovationTracker::new(), Rng::new(0));
+        let mut bank = GenomeBank::new();
+        for fitness in fitnesses {
+            let mut genome = Genome::new(2, 1, false, &mut innovations, &mut rng);
+            genome.fitness = Some(*fitness);
+            bank.add_genome(genome);
+        }
+        bank
+    }
+
+    fn collect(fitnesses: &[f64]) -> GenerationStatistics {
+        GenerationStatistics::collect(1, &bank(fitnesses), &SpeciesSet::new(), 3., 0, 0.)
+    }
+
+    #[test]
+    fn fitness_summary() {
+        let statistics = collect(&[4., 1., 3., 2.]);
+
+        assert_eq!(statistics.best_fitness, 4.);
+        assert_eq!(statistics.mean_fitness, 2.5);
+        assert_eq!(statistics.median_fitness, 2.5);
+        assert_eq!(statistics.stddev_fitness, 1.25f64.sqrt());
+        assert_eq!(statistics.mean_nodes, 3.);
+        assert_eq!(statistics.mean_connections, 2.);
+    }
+
+    #[test]
+    fn median_of_an_odd_count_is_the_middle_fitness() {
+        assert_eq!(collect(&[5., 1., 2.]).median_fitness, 2.);
+    }
+
+    #[test]
+    fn csv_has_a_row_per_generation() {
+        let mut statistics = Statistics::new();
+        statistics.push(collect(&[1., 2.]));
+        statistics.push(collect(&[3.]));
+        let csv = statistics.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.split(',').count() == 12));
+        assert!(rows[2].starts_with("1,3,3,3,0,"));
+    }
+}
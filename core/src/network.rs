@@ -1,5 +1,4 @@
-use godot::prelude::*;
-use nanoserde::{DeBin, SerBin};
+use nanoserde::{DeBin, DeBinErr, SerBin};
 
 use crate::activation::*;
 use crate::aggregations::aggregate;
@@ -7,79 +6,59 @@ use crate::connection::*;
 use crate::genome::Genome;
 use crate::node::*;
 
-#[derive(Debug, GodotClass, DeBin, SerBin)]
-#[class(base=RefCounted)]
+#[derive(Debug, Default, Clone)]
 pub struct Network {
-    #[export(get, set)]
     pub input_count: u32,
-    #[export(get, set)]
     pub output_count: u32,
     pub nodes: Vec<NeuralNode>,
     pub connections: Vec<Connection>,
+    /// Substrate coordinates of every node, empty unless the network was painted by HyperNEAT
+    pub node_positions: Vec<Vec<f64>>,
     node_calculation_order: Vec<u32>,
 }
-trait Pack {
-    fn pack(self) -> PackedByteArray
-    where
-        Self: Sized;
-}
 
-impl Pack for Vec<u8> {
-    fn pack(self) -> PackedByteArray {
-        let mut arr = PackedByteArray::new();
-        for i in self.into_iter() {
-            arr.push(i)
-        }
-        arr
+/// Version of the fields written after the ones every network has, networks saved before
+/// there were any end right after `node_calculation_order`
+const LAYOUT_VERSION: u8 = 1;
+
+impl SerBin for Network {
+    fn ser_bin(&self, output: &mut Vec<u8>) {
+        self.input_count.ser_bin(output);
+        self.output_count.ser_bin(output);
+        self.nodes.ser_bin(output);
+        self.connections.ser_bin(output);
+        self.node_calculation_order.ser_bin(output);
+        LAYOUT_VERSION.ser_bin(output);
+        self.node_positions.ser_bin(output);
     }
 }
 
-#[godot_api]
-impl RefCountedVirtual for Network {
-    fn init(_base: Base<RefCounted>) -> Self {
-        Network {
-            input_count: 0,
-            output_count: 0,
-            nodes: vec![],
-            connections: vec![],
-            node_calculation_order: vec![],
+impl DeBin for Network {
+    fn de_bin(offset: &mut usize, bytes: &[u8]) -> Result<Self, DeBinErr> {
+        let mut network = Network {
+            input_count: DeBin::de_bin(offset, bytes)?,
+            output_count: DeBin::de_bin(offset, bytes)?,
+            nodes: DeBin::de_bin(offset, bytes)?,
+            connections: DeBin::de_bin(offset, bytes)?,
+            node_calculation_order: DeBin::de_bin(offset, bytes)?,
+            node_positions: vec![],
+        };
+        if *offset < bytes.len() && u8::de_bin(offset, bytes)? >= 1 {
+            network.node_positions = DeBin::de_bin(offset, bytes)?;
         }
+        Ok(network)
     }
 }
 
-#[godot_api]
 impl Network {
-    /// look if it was a proper graph then this would be hard
-    #[func]
-    fn get_nodes(&self) -> VariantArray {
-        let mut nodes: Array<Gd<NeuralNode>> = Array::new();
-        let mut connections: Array<Gd<Connection>> = Array::new();
-        for node in &self.nodes {
-            nodes.push(Gd::new(node.clone()))
-        }
-        for connection in &self.connections {
-            connections.push(Gd::new(connection.clone()))
-        }
-        varray![nodes, connections]
-    }
-
-    #[func]
-    pub fn to_bytes(&self) -> PackedByteArray {
-        self.serialize_bin().pack()
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.serialize_bin()
     }
 
-    #[func]
-    pub fn from_bytes(bytes: PackedByteArray) -> Variant {
-        match Self::deserialize_bin(&bytes.to_vec()) {
-            Ok(new) => Gd::new(new).to_variant(),
-            Err(e) => {
-                godot_error!("deserializing failed: {e:#?}");
-                Variant::nil()
-            }
-        }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeBinErr> {
+        Self::deserialize_bin(bytes)
     }
 
-    #[func]
     pub fn is_node_ready(&self, index: u32) -> bool {
         let node = self.nodes.get(index as usize).unwrap();
 
@@ -94,21 +73,24 @@ impl Network {
         requirements_fullfilled && has_no_value
     }
 
-    #[func]
-    pub fn forward_pass(&mut self, inputs: PackedFloat64Array) -> PackedFloat64Array {
+    pub fn forward_pass(&mut self, inputs: &[f64]) -> Vec<f64> {
         for i in &self.node_calculation_order {
             let node = self.nodes.get(*i as usize).unwrap();
 
             if matches!(node.kind, NodeKind::Input) {
-                self.nodes.get_mut(*i as usize).unwrap().value = Some(inputs.get(*i as usize));
+                self.nodes.get_mut(*i as usize).unwrap().value = Some(inputs[*i as usize]);
+            } else if matches!(node.kind, NodeKind::Constant) {
+                // The bias node always outputs 1, its connection weights act as biases
+                self.nodes.get_mut(*i as usize).unwrap().value = Some(1.);
             } else {
                 let components: Vec<f64> = self
                     .connections
                     .iter()
                     .filter(|c| c.to == *i)
                     .map(|c| {
+                        // Recurrent connections carry the value from the previous pass
                         let incoming_value =
-                            self.nodes.get(c.from as usize).unwrap().value.unwrap();
+                            self.nodes.get(c.from as usize).unwrap().value.unwrap_or(0.);
                         incoming_value * c.weight
                     })
                     .collect();
@@ -121,37 +103,207 @@ impl Network {
             }
         }
 
-        let mut result = PackedFloat64Array::new();
         self.nodes
             .iter()
             .filter(|n| matches!(n.kind, NodeKind::Output))
             .map(|n| n.value.unwrap())
-            .for_each(|f| result.push(f));
-        result // note that result can be f32::NAN
+            .collect() // note that result can be f32::NAN
+    }
+
+    /// Substrate coordinates of the hidden nodes, to visualize where HyperNEAT placed them
+    pub fn hidden_positions(&self) -> Vec<&[f64]> {
+        self.nodes
+            .iter()
+            .zip(&self.node_positions)
+            .filter(|(node, _)| matches!(node.kind, NodeKind::Hidden))
+            .map(|(_, position)| position.as_slice())
+            .collect()
     }
 
-    #[func]
     pub fn clear_values(&mut self) {
         self.nodes.iter_mut().for_each(|n| n.value = None);
     }
-}
 
-impl Network {
-    pub fn from_genome(g: &Genome) -> Gd<Self> {
+    /// Forgets the node values kept between passes by recurrent connections
+    pub fn reset_state(&mut self) {
+        self.clear_values();
+    }
+
+    /// A network whose connections all go from a node to one listed after it,
+    /// the inputs coming first
+    pub fn feed_forward(
+        input_count: u32,
+        output_count: u32,
+        nodes: Vec<NeuralNode>,
+        connections: Vec<Connection>,
+    ) -> Self {
+        Network {
+            input_count,
+            output_count,
+            node_calculation_order: (0..nodes.len() as u32).collect(),
+            nodes,
+            connections,
+            node_positions: vec![],
+        }
+    }
+
+    pub fn from_genome(g: &Genome) -> Self {
+        // Genes reference node ids, the network references node indexes
+        let index_of = |id: u32| g.nodes().iter().position(|n| n.id == id).unwrap() as u32;
+
         let nodes: Vec<NeuralNode> = g.nodes().iter().map(From::from).collect();
         let connections: Vec<Connection> = g
             .connections()
             .iter()
             .filter(|c| !c.disabled)
-            .map(From::from)
+            .map(|c| Connection {
+                from: index_of(c.from),
+                to: index_of(c.to),
+                weight: c.weight,
+            })
             .collect();
+        let node_calculation_order: Vec<u32> =
+            g.node_order().unwrap().into_iter().map(index_of).collect();
 
-        Gd::new(Network {
+        Network {
             input_count: g.input_count(),
             output_count: g.output_count(),
             nodes,
             connections,
-            node_calculation_order: g.node_order().unwrap(),
-        })
+            node_positions: vec![],
+            node_calculation_order,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregations::Aggregation;
+
+    fn node(kind: NodeKind, activation: ActivationKind, bias: f64) -> NeuralNode {
+        NeuralNode {
+            kind,
+            aggregation: Aggregation::Sum,
+            activation,
+            bias,
+            value: None,
+        }
+    }
+
+    fn connection(from: u32, to: u32, weight: f64) -> Connection {
+        Connection { from, to, weight }
+    }
+
+    #[test]
+    fn forward_pass_sums_weighted_inputs_and_bias() {
+        let mut network = Network::feed_forward(
+            2,
+            1,
+            vec![
+                node(NodeKind::Input, ActivationKind::Input, 0.),
+                node(NodeKind::Input, ActivationKind::Input, 0.),
+                node(NodeKind::Output, ActivationKind::Identity, 0.5),
+            ],
+            vec![connection(0, 2, 1.), connection(1, 2, -2.)],
+        );
+
+        assert_eq!(network.forward_pass(&[3., 1.]), vec![1.5]);
+        assert_eq!(network.forward_pass(&[0., 0.]), vec![0.5]);
+    }
+
+    #[test]
+    fn forward_pass_goes_through_hidden_nodes() {
+        let mut network = Network::feed_forward(
+            1,
+            2,
+            vec![
+                node(NodeKind::Input, ActivationKind::Input, 0.),
+                node(NodeKind::Hidden, ActivationKind::Step, 0.),
+                node(NodeKind::Output, ActivationKind::Identity, 0.),
+                node(NodeKind::Output, ActivationKind::Identity, 0.),
+            ],
+            vec![
+                connection(0, 1, 1.),
+                connection(1, 2, 2.),
+                connection(0, 3, 1.),
+            ],
+        );
+
+        assert_eq!(network.forward_pass(&[0.5]), vec![2., 0.5]);
+        assert_eq!(network.forward_pass(&[-0.5]), vec![0., -0.5]);
+    }
+
+    #[test]
+    fn bias_node_always_outputs_one() {
+        let mut network = Network::feed_forward(
+            1,
+            1,
+            vec![
+                node(NodeKind::Input, ActivationKind::Input, 0.),
+                node(NodeKind::Constant, ActivationKind::Input, 0.),
+                node(NodeKind::Output, ActivationKind::Identity, 0.),
+            ],
+            vec![connection(0, 2, 1.), connection(1, 2, 0.5)],
+        );
+
+        assert_eq!(network.forward_pass(&[0.]), vec![0.5]);
+        assert_eq!(network.forward_pass(&[2.]), vec![2.5]);
+    }
+
+    /// One input connected to one output with a weight of 2
+    fn doubling_network() -> Network {
+        Network::feed_forward(
+            1,
+            1,
+            vec![
+                node(NodeKind::Input, ActivationKind::Input, 0.),
+                node(NodeKind::Output, ActivationKind::Identity, 0.),
+            ],
+            vec![connection(0, 1, 2.)],
+        )
+    }
+
+    #[test]
+    fn networks_saved_before_node_positions_still_load() {
+        let network = doubling_network();
+        let mut bytes = vec![];
+        network.input_count.ser_bin(&mut bytes);
+        network.output_count.ser_bin(&mut bytes);
+        network.nodes.ser_bin(&mut bytes);
+        network.connections.ser_bin(&mut bytes);
+        network.node_calculation_order.ser_bin(&mut bytes);
+
+        let mut loaded = Network::from_bytes(&bytes).unwrap();
+        assert!(loaded.node_positions.is_empty());
+        assert_eq!(loaded.forward_pass(&[1.5]), vec![3.]);
+    }
+
+    #[test]
+    fn node_positions_survive_serialization() {
+        let mut network = doubling_network();
+        network.node_positions = vec![vec![0., -1.], vec![0., 1.]];
+
+        let mut loaded = Network::from_bytes(&network.to_bytes()).unwrap();
+        assert_eq!(loaded.node_positions, network.node_positions);
+        assert_eq!(loaded.forward_pass(&[1.5]), vec![3.]);
+    }
+
+    #[test]
+    fn recurrent_connections_carry_the_previous_pass() {
+        let mut network = Network::feed_forward(
+            1,
+            1,
+            vec![
+                node(NodeKind::Input, ActivationKind::Input, 0.),
+                node(NodeKind::Output, ActivationKind::Identity, 0.),
+            ],
+            vec![connection(0, 1, 1.), connection(1, 1, 1.)],
+        );
+
+        assert_eq!(network.forward_pass(&[1.]), vec![1.]);
+        assert_eq!(network.forward_pass(&[1.]), vec![2.]);
+        network.reset_state();
+        assert_eq!(network.forward_pass(&[1.]), vec![1.]);
     }
 }
@@ -1,6 +1,7 @@
 use crate::activation::ActivationKind;
 use crate::aggregations::Aggregation;
 use crate::genome::node::NodeGene;
+#[cfg(feature = "godot")]
 use godot::prelude::*;
 use nanoserde::{DeBin, SerBin};
 
@@ -46,7 +47,8 @@ pub enum NodeKind {
     Constant,
 }
 
-#[derive(Debug, DeBin, SerBin, GodotClass, Clone)]
+#[derive(Debug, DeBin, SerBin, Clone)]
+#[cfg_attr(feature = "godot", derive(GodotClass))]
 pub struct NeuralNode {
     pub kind: NodeKind,
     pub aggregation: Aggregation,
@@ -55,34 +57,6 @@ pub struct NeuralNode {
     pub value: Option<f64>,
 }
 
-#[godot_api]
-impl NeuralNode {
-    #[func]
-    fn get_kind(&self) -> u8 {
-        self.kind.clone().to()
-    }
-    #[func]
-    fn get_aggregation(&self) -> u8 {
-        self.aggregation.clone().to()
-    }
-    #[func]
-    fn get_activation(&self) -> u8 {
-        self.activation.clone().to()
-    }
-    #[func]
-    fn get_bias(&self) -> f64 {
-        self.bias
-    }
-    /// null till i can get signals
-    #[func]
-    fn get_value(&self) -> Variant {
-        if self.value.is_none() {
-            return Variant::nil();
-        }
-        self.value.unwrap().to_variant()
-    }
-}
-
 impl From<&NodeGene> for NeuralNode {
     fn from(g: &NodeGene) -> Self {
         NeuralNode {
@@ -0,0 +1,64 @@
+//! Seedable random number generation, independent of any engine
+
+use nanoserde::{DeBin, SerBin};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// xorshift64* generator, every run owns one so identical seeds give identical runs
+#[derive(Debug, Clone, DeBin, SerBin)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64, so that similar seeds still give unrelated sequences
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        // Never zero, xorshift would get stuck
+        Rng { state: z.max(1) }
+    }
+
+    /// A seed taken from the system clock, for runs that don't care about reproducibility
+    pub fn entropy_seed() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A random 32 bit unsigned integer
+    pub fn randi(&mut self) -> i64 {
+        (self.next() >> 32) as i64
+    }
+
+    /// A random float in `[0, 1)`
+    pub fn randf(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A random integer in `[from, to]`
+    pub fn randi_range(&mut self, from: i64, to: i64) -> i64 {
+        from + (self.next() % (to - from + 1) as u64) as i64
+    }
+
+    /// A normally distributed float
+    pub fn randfn(&mut self, mean: f64, deviation: f64) -> f64 {
+        // Box-Muller transform, 1 - randf() is never zero
+        let u1 = 1. - self.randf();
+        let u2 = self.randf();
+
+        mean + deviation * (-2. * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
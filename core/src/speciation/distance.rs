@@ -1,28 +1,29 @@
-use godot::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 
+use crate::genome::GenomeId;
 use crate::Configuration;
 use crate::{ConnectionGene, Genome};
 
-pub struct GenomicDistanceCache {
-    configuration: Gd<Configuration>,
-    cache: Dictionary,
+pub struct GenomicDistanceCache<'a> {
+    configuration: &'a Configuration,
+    cache: HashMap<(GenomeId, GenomeId), f64>,
 }
 
-impl GenomicDistanceCache {
-    pub fn new(configuration: Gd<Configuration>) -> Self {
+impl<'a> GenomicDistanceCache<'a> {
+    pub fn new(configuration: &'a Configuration) -> Self {
         GenomicDistanceCache {
             configuration,
-            cache: Dictionary::new(),
+            cache: HashMap::new(),
         }
     }
 
-    pub fn get(&mut self, a: Gd<Genome>, b: Gd<Genome>) -> f64 {
-        let distance_key = GodotString::from(GenomicDistanceCache::make_key(&a.bind(), &b.bind()));
+    pub fn get(&mut self, a: &Genome, b: &Genome) -> f64 {
+        let distance_key = GenomicDistanceCache::make_key(a, b);
 
-        if let Some(distance) = self.cache.get(distance_key.clone()) {
-            f64::from_variant(&distance)
+        if let Some(distance) = self.cache.get(&distance_key) {
+            *distance
         } else {
-            let distance = self.distance(&a.bind(), &b.bind());
+            let distance = self.distance(a, b);
             self.cache.insert(distance_key, distance);
 
             distance
@@ -38,7 +39,7 @@ impl GenomicDistanceCache {
             distance_node_activation_coefficient,
             distance_node_aggregation_coefficient,
         ) = {
-            let conf = self.configuration.bind();
+            let conf = self.configuration;
 
             (
                 conf.distance_connection_disjoint_coefficient,
@@ -57,28 +58,25 @@ impl GenomicDistanceCache {
         let mut disjoint_connections: Vec<&ConnectionGene> = vec![];
         let mut common_connections: Vec<(&ConnectionGene, &ConnectionGene)> = vec![];
 
-        let mut disjoint_map = Dictionary::new(); // Dictionary<usize, bool>
+        let mut disjoint_map: BTreeMap<u32, bool> = BTreeMap::new();
         a.connections()
             .iter()
             .chain(b.connections().iter())
-            .map(|connection| connection.innovation_number())
+            .map(|connection| connection.innovation)
             .for_each(|innovation_number| {
-                disjoint_map.insert(
-                    innovation_number,
-                    !disjoint_map.contains_key(innovation_number),
-                );
+                let is_disjoint = !disjoint_map.contains_key(&innovation_number);
+                disjoint_map.insert(innovation_number, is_disjoint);
             });
 
         disjoint_map
-            .iter_shared()
-            .map(|(x, y)| (u32::from_variant(&x), bool::from_variant(&y)))
+            .into_iter()
             .for_each(|(innovation_number, is_disjoint)| {
                 if is_disjoint {
                     let disjoint_connection = a
                         .connections()
                         .iter()
                         .chain(b.connections().iter())
-                        .find(|connection| connection.innovation_number() == innovation_number)
+                        .find(|connection| connection.innovation == innovation_number)
                         .unwrap();
 
                     disjoint_connections.push(disjoint_connection);
@@ -86,12 +84,12 @@ impl GenomicDistanceCache {
                     let common_connection_a = a
                         .connections()
                         .iter()
-                        .find(|connection| connection.innovation_number() == innovation_number)
+                        .find(|connection| connection.innovation == innovation_number)
                         .unwrap();
                     let common_connection_b = b
                         .connections()
                         .iter()
-                        .find(|connection| connection.innovation_number() == innovation_number)
+                        .find(|connection| connection.innovation == innovation_number)
                         .unwrap();
 
                     common_connections.push((common_connection_a, common_connection_b));
@@ -117,10 +115,11 @@ impl GenomicDistanceCache {
             })
             .sum::<f64>();
 
+        // Only homologous nodes are compared
         let nodes_difference_factor: f64 = a
             .nodes()
             .iter()
-            .zip(b.nodes())
+            .filter_map(|node_a| Some((node_a, b.node(node_a.id)?)))
             .map(|(node_a, node_b)| {
                 let mut node_distance = 0.;
 
@@ -139,7 +138,11 @@ impl GenomicDistanceCache {
             .sum();
 
         distance += nodes_difference_factor;
-        distance += (connections_difference_factor + disjoint_factor) / max_connection_genes as f64;
+        // Genomes that start unconnected have no connection genes to compare
+        if max_connection_genes > 0 {
+            distance +=
+                (connections_difference_factor + disjoint_factor) / max_connection_genes as f64;
+        }
 
         distance
     }
@@ -148,13 +151,38 @@ impl GenomicDistanceCache {
     //     self.cache.values().sum::<f64>() / self.cache.len() as f64
     // }
 
-    fn make_key(a: &Genome, b: &Genome) -> String {
+    fn make_key(a: &Genome, b: &Genome) -> (GenomeId, GenomeId) {
         let a = a.id();
         let b = b.id();
-        if i64::from(a) > i64::from(b) {
-            a.full_str() + b.full_str().as_str()
+        if a > b {
+            (a, b)
         } else {
-            b.full_str() + a.full_str().as_str()
+            (b, a)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genome::InnovationTracker;
+    use crate::random::Rng;
+
+    #[test]
+    fn genomes_without_connections_are_compared_by_their_nodes() {
+        let configuration = Configuration::default();
+        let mut cache = GenomicDistanceCache::new(&configuration);
+        let (mut innovations, mut rng) = (InnovationTracker::new(), Rng::new(0));
+        let mut a = Genome::unconnected(2, 1, false, &mut innovations, &mut rng);
+        a.node_mut(2).unwrap().bias = 0.25;
+        let mut b = a.duplicate(&mut rng);
+        assert_eq!(cache.get(&a, &b), 0.);
+
+        b.node_mut(2).unwrap().bias = 1.25;
+        let b = b.duplicate(&mut rng);
+        assert_eq!(
+            cache.get(&a, &b),
+            configuration.distance_node_bias_coefficient
+        );
+    }
+}
@@ -1,35 +1,36 @@
 use crate::genome::GenomeId;
-use crate::Configuration;
-use crate::GenomeMap;
-use crate::Map;
-use crate::{bind, bind_mut};
-use godot::prelude::*;
+use crate::neat::GenomeBank;
+use crate::{Configuration, OffspringAllocation};
+use nanoserde::{DeBin, SerBin};
+use std::collections::BTreeMap;
 
-pub type SpeciesMap = Map<u32, Species>;
+pub type SpeciesMap = BTreeMap<u32, Species>;
 use distance::GenomicDistanceCache;
 
 mod distance;
 
-#[derive(GodotClass)]
-#[class(base=RefCounted)]
+#[derive(Debug, Default, Clone)]
 pub struct SpeciesSet {
-    configuration: Gd<Configuration>,
     // last_index: Option<usize>,
     species: SpeciesMap,
+    /// Adjusted towards `target_species_count`, starts at the configured threshold
+    compatibility_threshold: Option<f64>,
 }
 
-#[godot_api]
 impl SpeciesSet {
-    #[func]
-    fn get_species(&self) -> Dictionary {
-        self.species.d()
+    pub fn new() -> Self {
+        SpeciesSet {
+            // last_index: None,
+            species: SpeciesMap::new(),
+            compatibility_threshold: None,
+        }
     }
 
-    pub fn new(configuration: Gd<Configuration>) -> Self {
+    /// Species as they were after a speciation, with the threshold it had moved to
+    pub fn from_species(species: SpeciesMap, compatibility_threshold: Option<f64>) -> Self {
         SpeciesSet {
-            configuration,
-            // last_index: None,
-            species: Map::new(),
+            species,
+            compatibility_threshold,
         }
     }
 
@@ -37,43 +38,45 @@ impl SpeciesSet {
         &self.species
     }
 
+    /// The threshold moved towards `target_species_count`, `None` while the configured one is used
+    pub fn adjusted_compatibility_threshold(&self) -> Option<f64> {
+        self.compatibility_threshold
+    }
+
+    /// The threshold the next speciation will use
+    pub fn compatibility_threshold(&self, configuration: &Configuration) -> f64 {
+        self.compatibility_threshold
+            .unwrap_or(configuration.compatibility_threshold)
+    }
+
+    /// Sorts the current genomes into species, returns how many stagnated species were removed
     pub fn speciate(
         &mut self,
+        configuration: &Configuration,
         generation: u32,
-        current_genomes: &[GenomeId],
-        all_genomes: GenomeMap,
-    ) {
-        let (compatibility_threshold, stagnation_after, elitism_species) = {
-            let config = self.configuration.bind();
-
-            (
-                config.compatibility_threshold,
-                config.stagnation_after,
-                config.elitism_species,
-            )
-        };
-
-        let mut distances = GenomicDistanceCache::new(self.configuration.share());
-
-        let mut unspeciated_genomes = Dictionary::new();
-        for g in current_genomes.iter() {
-            unspeciated_genomes.set(*g, Variant::nil())
-        }
+        genomes: &GenomeBank,
+    ) -> usize {
+        let (compatibility_threshold, stagnation_after, elitism_species) = (
+            self.compatibility_threshold(configuration),
+            configuration.stagnation_after,
+            configuration.elitism_species,
+        );
+
+        let mut distances = GenomicDistanceCache::new(configuration);
+
+        let current_genomes: Vec<GenomeId> = genomes.genomes().keys().copied().collect();
+        let mut unspeciated_genomes = current_genomes.clone();
         let mut new_species = self.species.clone();
 
         // Find new representatives for existing species
         self.species.iter().for_each(|(species_id, species)| {
-            bind!(species);
-            let genome_representative = all_genomes.get(species.representative).unwrap();
+            let genome_representative = genomes.find(species.representative).unwrap();
 
             let (maybe_new_representative_id, _) = current_genomes
                 .iter()
                 .map(|genome_id| {
-                    let genome = all_genomes.get(*genome_id).unwrap();
-                    (
-                        genome_id,
-                        distances.get(genome, genome_representative.share()),
-                    )
+                    let genome = genomes.get(*genome_id).unwrap();
+                    (genome_id, distances.get(genome, genome_representative))
                 })
                 .filter(|(_, distance)| *distance < compatibility_threshold)
                 .fold(
@@ -93,32 +96,29 @@ impl SpeciesSet {
                 );
 
             if let Some(new_representative_id) = maybe_new_representative_id {
-                let mut species = new_species.get(species_id).unwrap();
-                species.bind_mut().representative = *new_representative_id;
-                species.bind_mut().members = vec![*new_representative_id];
-                new_species.set(species_id, species);
+                let species = new_species.get_mut(species_id).unwrap();
+                species.representative = *new_representative_id;
+                species.members = vec![*new_representative_id];
 
-                unspeciated_genomes.remove(*new_representative_id);
+                unspeciated_genomes.retain(|genome_id| genome_id != new_representative_id);
             } else {
-                new_species.erase(species_id);
+                new_species.remove(species_id);
             }
         });
 
         // Put unspeciated genomes into species
-        unspeciated_genomes.keys_shared().for_each(|genome_id| {
-            let genome_id = GenomeId::from_variant(&genome_id);
-            let genome = all_genomes.get(genome_id).unwrap();
+        unspeciated_genomes.into_iter().for_each(|genome_id| {
+            let genome = genomes.get(genome_id).unwrap();
 
             let (maybe_closest_species_id, _) = {
                 new_species
                     .iter()
                     .map(|(species_id, species)| {
-                        bind!(species);
                         let species_representative_genome =
-                            all_genomes.get(species.representative).unwrap();
+                            genomes.find(species.representative).unwrap();
                         (
-                            species_id,
-                            distances.get(genome.share(), species_representative_genome),
+                            *species_id,
+                            distances.get(genome, species_representative_genome),
                         )
                     })
                     .filter(|(_, distance)| *distance < compatibility_threshold)
@@ -142,34 +142,28 @@ impl SpeciesSet {
             if let Some(closest_species_id) = maybe_closest_species_id {
                 // Fits into an existing species
                 new_species
-                    .get(closest_species_id)
+                    .get_mut(&closest_species_id)
                     .unwrap()
-                    .bind_mut()
                     .members
                     .push(genome_id);
             } else {
                 // Needs to go in a brand new species
                 let species = Species::new(generation, genome_id, vec![genome_id]);
-                let next_species_id = self.species.keys().max().unwrap_or(0) + 1;
+                let next_species_id = u32::max(
+                    self.species.keys().max().copied().unwrap_or(0),
+                    new_species.keys().max().copied().unwrap_or(0),
+                ) + 1;
 
-                new_species.set(next_species_id, Gd::new(species));
+                new_species.insert(next_species_id, species);
             }
         });
 
         // Calculate fitness for every species
-        new_species.values().for_each(|mut species| {
-            bind_mut!(species);
+        new_species.values_mut().for_each(|species| {
             let member_fitnesses: Vec<f64> = species
                 .members
                 .iter()
-                .map(|member_genome_id| {
-                    all_genomes
-                        .get(*member_genome_id)
-                        .unwrap()
-                        .bind()
-                        .fitness
-                        .unwrap()
-                })
+                .map(|member_genome_id| genomes.get(*member_genome_id).unwrap().score())
                 .collect();
 
             let species_mean_fitness =
@@ -188,50 +182,103 @@ impl SpeciesSet {
             species.fitness_history.push(species_mean_fitness);
         });
 
-        // Calculate adjusted fitness for every species
-        let species_fitnesses: Vec<f64> = new_species
-            .values()
-            .map(|species| species.bind().fitness.unwrap())
-            .collect();
-        let exp_sum: f64 = species_fitnesses.iter().map(|fitness| fitness.exp()).sum();
-        new_species.values().for_each(|mut species| {
-            bind_mut!(species);
-            let own_exp = species.fitness.unwrap().exp();
-            let adjusted_fitness = own_exp / exp_sum;
-            species.adjusted_fitness = Some(adjusted_fitness);
-        });
-
-        // Remove stagnated species
-        let mut stagnated_ids_and_adjusted_fitnesses: Vec<(u32, f64)> = new_species
+        // Remove stagnated species, every allocation strategy ranks species by their fitness
+        let mut stagnated_ids_and_fitnesses: Vec<(u32, f64)> = new_species
             .iter()
-            .filter(|(_, species)| generation - species.bind().last_improved >= stagnation_after)
-            .map(|(id, species)| (id, species.bind().adjusted_fitness.unwrap()))
+            .filter(|(_, species)| generation - species.last_improved >= stagnation_after)
+            .map(|(id, species)| (*id, species.fitness.unwrap()))
             .collect();
 
-        stagnated_ids_and_adjusted_fitnesses.sort_by(|(_, a), (_, b)| {
-            use std::cmp::Ordering::*;
-
-            if a > b {
-                Less
-            } else {
-                Greater
-            }
-        });
+        stagnated_ids_and_fitnesses.sort_by(|(_, a), (_, b)| b.total_cmp(a));
 
-        stagnated_ids_and_adjusted_fitnesses
+        let removable_count = new_species.len().saturating_sub(elitism_species as usize);
+        let removed_count = usize::min(removable_count, stagnated_ids_and_fitnesses.len());
+        stagnated_ids_and_fitnesses
             .into_iter()
-            .take(usize::max(new_species.len() - elitism_species as usize, 0))
+            .take(removable_count)
             .for_each(|(id, _)| {
-                new_species.remove(id).unwrap();
+                new_species.remove(&id).unwrap();
             });
 
+        // Calculate adjusted fitness, the share of offspring, for every surviving species
+        allocate_offspring(configuration.offspring_allocation, &mut new_species);
+
         // Finally replace old species
         self.species = new_species;
+
+        // Move the threshold so the next generation gets closer to the wanted number of species
+        if configuration.target_species_count > 0 {
+            let species_count = self.species.len() as u32;
+            let step = configuration.compatibility_threshold_step;
+            let threshold = if species_count < configuration.target_species_count {
+                compatibility_threshold - step
+            } else if species_count > configuration.target_species_count {
+                compatibility_threshold + step
+            } else {
+                compatibility_threshold
+            };
+            self.compatibility_threshold = Some(threshold.max(step));
+        }
+
+        removed_count
     }
 }
 
-#[derive(Clone, GodotClass)]
-#[class(base=RefCounted)]
+/// How far below the worst species negative fitnesses are shifted, relative to the fitness spread
+const NEGATIVE_FITNESS_MARGIN: f64 = 0.1;
+
+fn allocate_offspring(strategy: OffspringAllocation, species: &mut SpeciesMap) {
+    let fitnesses: Vec<f64> = species
+        .values()
+        .map(|species| species.fitness.unwrap())
+        .collect();
+
+    let weights: Vec<f64> = match strategy {
+        OffspringAllocation::FitnessSharing => {
+            // Member fitnesses divided by the species size sum up to the mean fitness,
+            // negative fitnesses are shifted so no species gets a negative share. The shift goes
+            // a bit below the worst species so it still gets offspring, with only two species
+            // shifting to exactly its fitness would hand everything to the other one
+            let min = fitnesses.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = fitnesses.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let offset = if min < 0. {
+                min - (max - min) * NEGATIVE_FITNESS_MARGIN
+            } else {
+                0.
+            };
+            fitnesses.iter().map(|fitness| fitness - offset).collect()
+        }
+        OffspringAllocation::Softmax => {
+            // Shifting by the best fitness keeps the exponents from overflowing
+            let max = fitnesses.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            fitnesses
+                .iter()
+                .map(|fitness| (fitness - max).exp())
+                .collect()
+        }
+        OffspringAllocation::Rank => fitnesses
+            .iter()
+            .map(|fitness| fitnesses.iter().filter(|other| *other < fitness).count() as f64 + 1.)
+            .collect(),
+    };
+
+    let weight_sum: f64 = weights.iter().sum();
+    let species_count = weights.len() as f64;
+    species
+        .values_mut()
+        .zip(weights)
+        .for_each(|(species, weight)| {
+            let adjusted_fitness = if weight_sum > 0. {
+                weight / weight_sum
+            } else {
+                // All species are equally good
+                1. / species_count
+            };
+            species.adjusted_fitness = Some(adjusted_fitness);
+        });
+}
+
+#[derive(Clone, DeBin, SerBin)]
 pub struct Species {
     // created: usize,
     last_improved: u32,
@@ -243,13 +290,6 @@ pub struct Species {
     fitness_history: Vec<f64>,
 }
 
-#[godot_api]
-impl RefCountedVirtual for Species {
-    fn to_string(&self) -> GodotString {
-        GodotString::from(format!("{self:?}"))
-    }
-}
-
 impl std::fmt::Debug for Species {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Species<{}>", self.representative.uuid_str())
@@ -268,4 +308,103 @@ impl Species {
             fitness_history: vec![],
         }
     }
+
+    pub fn last_improved(&self) -> u32 {
+        self.last_improved
+    }
+
+    pub fn representative(&self) -> GenomeId {
+        self.representative
+    }
+
+    pub fn fitness(&self) -> Option<f64> {
+        self.fitness
+    }
+
+    pub fn fitness_history(&self) -> &[f64] {
+        &self.fitness_history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    fn allocate(strategy: OffspringAllocation, fitnesses: &[f64]) -> Vec<f64> {
+        let mut rng = Rng::new(0);
+        let mut species: SpeciesMap = fitnesses
+            .iter()
+            .enumerate()
+            .map(|(id, fitness)| {
+                let mut species = Species::new(0, GenomeId::new(&mut rng), vec![]);
+                species.fitness = Some(*fitness);
+                (id as u32, species)
+            })
+            .collect();
+
+        allocate_offspring(strategy, &mut species);
+        species
+            .values()
+            .map(|species| species.adjusted_fitness.unwrap())
+            .collect()
+    }
+
+    fn assert_close(actual: Vec<f64>, expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() < 1e-9,
+                "{} != {}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn fitness_sharing_is_proportional_to_fitness() {
+        assert_close(
+            allocate(OffspringAllocation::FitnessSharing, &[1., 3.]),
+            &[0.25, 0.75],
+        );
+    }
+
+    #[test]
+    fn fitness_sharing_keeps_the_worst_species_with_negative_fitness() {
+        let shares = allocate(OffspringAllocation::FitnessSharing, &[-3., -1.]);
+
+        assert!(shares[0] > 0.);
+        assert!(shares[1] > shares[0]);
+        assert_close(shares, &[0.2 / 2.4, 2.2 / 2.4]);
+    }
+
+    #[test]
+    fn softmax_is_proportional_to_the_exponent() {
+        let e = 1f64.exp();
+        assert_close(
+            allocate(OffspringAllocation::Softmax, &[0., 1.]),
+            &[1. / (1. + e), e / (1. + e)],
+        );
+    }
+
+    #[test]
+    fn rank_ignores_the_scale_of_fitness() {
+        assert_close(
+            allocate(OffspringAllocation::Rank, &[1000., -5., 0.]),
+            &[0.5, 1. / 6., 2. / 6.],
+        );
+    }
+
+    #[test]
+    fn softmax_handles_large_fitnesses() {
+        assert_close(
+            allocate(OffspringAllocation::Softmax, &[1000., 0.]),
+            &[1., 0.],
+        );
+        assert_close(
+            allocate(OffspringAllocation::Softmax, &[1000., 1000.]),
+            &[0.5, 0.5],
+        );
+    }
 }
@@ -1,3 +1,5 @@
 #!/bin/bash
 (cd core && cargo build "$@")
+# The algorithm is tested without the engine, the Godot project covers the bindings
+(cd core && cargo test --no-default-features)
 (cd tests && godot4 --headless)